@@ -0,0 +1,151 @@
+use itertools::Itertools;
+
+use crate::generate::CharToFinger;
+use crate::utility::is_left_hand;
+
+/// What breaking a hard constraint costs, which is far more than the rest of a score can make up
+/// for, so layouts only break one when there's no other way.
+pub const HARD_PENALTY: f64 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hand {
+	Left,
+	Right
+}
+
+impl TryFrom<&str> for Hand {
+	type Error = anyhow::Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		match value.trim().to_lowercase().as_str() {
+			"left" => Ok(Self::Left),
+			"right" => Ok(Self::Right),
+			_ => anyhow::bail!("'{value}' isn't a hand, use left or right")
+		}
+	}
+}
+
+impl std::fmt::Display for Hand {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Left => write!(f, "left"),
+			Self::Right => write!(f, "right")
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rule {
+	/// Every character is on one of the fingers.
+	Fingers(Vec<char>, Vec<usize>),
+	/// The characters are all on the same hand.
+	SameHand(Vec<char>),
+	/// The characters are on different hands.
+	DifferentHands(char, char),
+	/// At most this many of the characters are on the hand.
+	MaxOnHand(Vec<char>, Hand, usize)
+}
+
+/// A rule for where characters go. Characters that aren't on a layout are left out of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraint {
+	pub rule: Rule,
+	/// What breaking the rule costs, `None` for a hard constraint.
+	pub penalty: Option<f64>
+}
+
+impl Constraint {
+	pub fn hard(rule: Rule) -> Self {
+		Self { rule, penalty: None }
+	}
+
+	pub fn soft(rule: Rule, penalty: f64) -> Self {
+		Self { rule, penalty: Some(penalty) }
+	}
+
+	pub fn penalty(&self) -> f64 {
+		self.penalty.unwrap_or(HARD_PENALTY)
+	}
+
+	/// The characters the rule is about.
+	pub fn chars(&self) -> Vec<char> {
+		match &self.rule {
+			Rule::Fingers(chars, _) | Rule::SameHand(chars) | Rule::MaxOnHand(chars, _, _) => chars.clone(),
+			Rule::DifferentHands(c1, c2) => vec![*c1, *c2]
+		}
+	}
+
+	pub fn is_broken(&self, char_to_finger: &CharToFinger<char>) -> bool {
+		let fingers = |chars: &[char]| chars.iter()
+			.filter_map(|c| char_to_finger.get(c).copied())
+			.collect::<Vec<_>>();
+
+		match &self.rule {
+			Rule::Fingers(chars, allowed) => fingers(chars).iter().any(|f| !allowed.contains(f)),
+			Rule::SameHand(chars) => fingers(chars).iter()
+				.map(|&f| is_left_hand(f))
+				.tuple_windows()
+				.any(|(h1, h2)| h1 != h2),
+			Rule::DifferentHands(c1, c2) => match (char_to_finger.get(c1), char_to_finger.get(c2)) {
+				(Some(&f1), Some(&f2)) => is_left_hand(f1) == is_left_hand(f2),
+				_ => false
+			},
+			Rule::MaxOnHand(chars, hand, max) => {
+				let on_hand = fingers(chars).iter()
+					.filter(|&&f| is_left_hand(f) == (*hand == Hand::Left))
+					.count();
+				on_hand > *max
+			}
+		}
+	}
+}
+
+impl std::fmt::Display for Constraint {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let list = |chars: &[char]| chars.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ");
+
+		match &self.rule {
+			Rule::Fingers(chars, fingers) => {
+				let fingers = fingers.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(", ");
+				write!(f, "{} on fingers {fingers}", list(chars))?
+			},
+			Rule::SameHand(chars) => write!(f, "{} on the same hand", list(chars))?,
+			Rule::DifferentHands(c1, c2) => write!(f, "{c1}, {c2} on different hands")?,
+			Rule::MaxOnHand(chars, hand, max) => write!(f, "at most {max} of {} on the {hand} hand", list(chars))?
+		}
+		match self.penalty {
+			Some(penalty) => write!(f, " (penalty {penalty})"),
+			None => write!(f, " (hard)")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::FastLayout;
+
+	#[test]
+	fn broken() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let broken = |rule| Constraint::hard(rule).is_broken(&qwerty.char_to_finger);
+
+		assert!(!broken(Rule::Fingers(vec!['e', 'i'], vec![2, 5])));
+		assert!(broken(Rule::Fingers(vec!['e', 'a'], vec![2, 5])));
+		assert!(!broken(Rule::Fingers(vec!['é'], vec![2])));
+
+		assert!(!broken(Rule::SameHand(vec!['a', 's', 'e'])));
+		assert!(broken(Rule::SameHand(vec!['a', 's', 'n'])));
+		assert!(!broken(Rule::DifferentHands('e', 'n')));
+		assert!(broken(Rule::DifferentHands('e', 'a')));
+		assert!(!broken(Rule::DifferentHands('e', 'é')));
+
+		assert!(!broken(Rule::MaxOnHand(vec!['a', 'e', 'i', 'o', 'u'], Hand::Left, 2)));
+		assert!(broken(Rule::MaxOnHand(vec!['a', 'e', 'i', 'o', 'u'], Hand::Right, 2)));
+
+		let constraint = Constraint::soft(Rule::MaxOnHand(vec!['a', 'e'], Hand::Left, 1), 0.5);
+		assert_eq!(constraint.to_string(), "at most 1 of a, e on the left hand (penalty 0.5)");
+		assert_eq!(constraint.penalty(), 0.5);
+		assert_eq!(Constraint::hard(Rule::DifferentHands('n', 'r')).to_string(), "n, r on different hands (hard)");
+	}
+}
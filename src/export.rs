@@ -0,0 +1,151 @@
+use anyhow::Result;
+
+use crate::layout::FastLayout;
+use crate::utility::LayoutShape;
+
+mod firmware;
+mod os;
+
+pub use firmware::{qmk_keymap, zmk_keymap, kanata_config};
+pub use os::{xkb_symbols, klc_layout, keylayout};
+pub(crate) use os::{PhysicalKey, PHYSICAL_KEYS, KEYSYM_NAMES};
+
+/// The keys of a keyboard in the order its firmware lists them, one row at a time. Every key holds
+/// the layout position on it, or `None` for keys the layout doesn't cover.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Geometry {
+	/// The QMK layout macro the keys are passed to.
+	pub layout_macro: String,
+	pub rows: Vec<Vec<Option<usize>>>
+}
+
+impl Geometry {
+	pub fn new(layout_macro: &str, rows: Vec<Vec<Option<usize>>>) -> Self {
+		Self { layout_macro: layout_macro.to_string(), rows }
+	}
+
+	/// Every key of the shape in the order of the layout, with the thumb keys as the last row.
+	pub fn from_shape(shape: LayoutShape) -> Self {
+		let mut rows = (0..shape.rows)
+			.map(|row| (row * shape.cols..(row + 1) * shape.cols).map(Some).collect())
+			.collect::<Vec<_>>();
+
+		if shape.thumbs > 0 {
+			rows.push((shape.grid_len()..shape.len()).map(Some).collect());
+		}
+		Self::new("LAYOUT", rows)
+	}
+}
+
+/// What a position on a layout does when it's pressed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Action {
+	/// A key on a US keyboard, with shift held or not.
+	Key(char, bool),
+	/// Holds the layer with this index, 0 being the base layer.
+	Layer(usize),
+	/// Falls through to the layer below.
+	Transparent
+}
+
+/// The key on a US keyboard that types `c`, and whether it needs shift. The shifted symbols are
+/// the ones `Translator` unshifts.
+pub(crate) fn us_key(c: char) -> Option<(char, bool)> {
+	const UNSHIFTED: &str = "`1234567890-=[]\\;',./";
+	const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+
+	match c {
+		'a'..='z' | '0'..='9' | ' ' => Some((c, false)),
+		'A'..='Z' => Some((c.to_ascii_lowercase(), true)),
+		_ if UNSHIFTED.contains(c) => Some((c, false)),
+		_ => SHIFTED.chars()
+			.position(|s| s == c)
+			.and_then(|i| UNSHIFTED.chars().nth(i))
+			.map(|base| (base, true))
+	}
+}
+
+/// The action of every position on every layer, the base layer first. Layer keys hold their layer,
+/// empty positions on a layer are transparent.
+pub(crate) fn layer_actions(layout: &FastLayout) -> Result<Vec<Vec<Action>>> {
+	let action = |c: char, on_layer: bool| {
+		let layer = layout.layers.iter().position(|l| l.key == c);
+
+		if let Some(i) = layer && !on_layer {
+			Ok(Action::Layer(i + 1))
+		} else if on_layer && c == ' ' {
+			Ok(Action::Transparent)
+		} else if let Some((key, shifted)) = us_key(c) {
+			Ok(Action::Key(key, shifted))
+		} else {
+			anyhow::bail!("'{c}' isn't on a US keyboard, so there is no keycode for it")
+		}
+	};
+
+	std::iter::once(&layout.matrix)
+		.chain(layout.layers.iter().map(|l| &l.matrix))
+		.enumerate()
+		.map(|(i, matrix)| matrix.iter().map(|&c| action(c, i > 0)).collect())
+		.collect()
+}
+
+/// Names of the layers for formats that name them.
+pub(crate) fn layer_names(layout: &FastLayout) -> Vec<String> {
+	std::iter::once("base".to_string())
+		.chain(layout.layers.iter().map(|l| l.name.clone()))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::layout::layers_from_kb;
+
+	/// Compares with a file in `static/golden`. Running the tests with `UPDATE_GOLDEN=1` writes the
+	/// file instead, which should be checked by hand before it's committed.
+	pub(crate) fn golden(name: &str, output: &str) {
+		let path = std::path::Path::new("static/golden").join(name);
+
+		if std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1") {
+			std::fs::create_dir_all("static/golden").unwrap();
+			std::fs::write(&path, output).unwrap();
+			return
+		}
+		match std::fs::read_to_string(&path) {
+			Ok(expected) => assert_eq!(output, expected, "{name} doesn't match its golden file"),
+			Err(e) => panic!("couldn't read the golden file of {name}, {e}. Run with UPDATE_GOLDEN=1 to write it")
+		}
+	}
+
+	pub(crate) fn layered_qwerty() -> FastLayout {
+		let layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.^").unwrap();
+		let layers = layers_from_kb(
+			"layer sym ^\n! @ # $ %  ^ & * ( )\n1 2 3 4 5  6 7 8 9 0\n_ _ _ _ _  - = { } _",
+			layout.shape
+		).unwrap();
+		layout.with_layers(layers)
+	}
+
+	#[test]
+	fn us_keys() {
+		assert_eq!(us_key('a'), Some(('a', false)));
+		assert_eq!(us_key('Q'), Some(('q', true)));
+		assert_eq!(us_key('{'), Some(('[', true)));
+		assert_eq!(us_key('"'), Some(('\'', true)));
+		assert_eq!(us_key('('), Some(('9', true)));
+		assert_eq!(us_key('é'), None);
+	}
+
+	#[test]
+	fn actions() {
+		let actions = layer_actions(&layered_qwerty()).unwrap();
+		assert_eq!(actions.len(), 2);
+		assert_eq!(actions[0][29], Action::Layer(1));
+		assert_eq!(actions[1][0], Action::Key('1', true));
+		assert_eq!(actions[1][5], Action::Key('6', true));
+		assert_eq!(actions[1][20], Action::Transparent);
+
+		let unknown = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.é").unwrap();
+		assert!(layer_actions(&unknown).is_err());
+	}
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+
+use super::*;
+
+fn qmk_key(key: char) -> String {
+	let name = match key {
+		'a'..='z' | '0'..='9' => key.to_ascii_uppercase().to_string(),
+		' ' => "SPC".into(),
+		'`' => "GRV".into(),
+		'-' => "MINS".into(),
+		'=' => "EQL".into(),
+		'[' => "LBRC".into(),
+		']' => "RBRC".into(),
+		'\\' => "BSLS".into(),
+		';' => "SCLN".into(),
+		'\'' => "QUOT".into(),
+		',' => "COMM".into(),
+		'.' => "DOT".into(),
+		'/' => "SLSH".into(),
+		_ => unreachable!("'{key}' isn't a key on a US keyboard")
+	};
+	format!("KC_{name}")
+}
+
+fn zmk_key(key: char) -> String {
+	match key {
+		'a'..='z' => key.to_ascii_uppercase().to_string(),
+		'0'..='9' => format!("N{key}"),
+		' ' => "SPACE".into(),
+		'`' => "GRAVE".into(),
+		'-' => "MINUS".into(),
+		'=' => "EQUAL".into(),
+		'[' => "LBKT".into(),
+		']' => "RBKT".into(),
+		'\\' => "BSLH".into(),
+		';' => "SEMI".into(),
+		'\'' => "SQT".into(),
+		',' => "COMMA".into(),
+		'.' => "DOT".into(),
+		'/' => "SLASH".into(),
+		_ => unreachable!("'{key}' isn't a key on a US keyboard")
+	}
+}
+
+fn kanata_key(key: char) -> String {
+	match key {
+		' ' => "spc".into(),
+		'`' => "grv".into(),
+		_ => key.to_string()
+	}
+}
+
+/// Kanata name of the physical key on a US keyboard at every position of the shape. Outer pinky
+/// columns are tab, caps and shift on the left and brackets, quote and shift on the right.
+fn kanata_src(shape: LayoutShape) -> Vec<String> {
+	const MAIN: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl;", "zxcvbnm,./"];
+	const LEFT: [&str; 4] = ["grv", "tab", "caps", "lsft"];
+	const RIGHT: [&str; 4] = ["-", "[", "'", "rsft"];
+
+	let mut res = Vec::new();
+	for i in 0..shape.grid_len() {
+		let (col, row) = (i % shape.cols, i / shape.cols + 1 - shape.row_offset());
+		let name = match col.checked_sub(shape.col_offset()) {
+			None => LEFT[row].to_string(),
+			Some(10) => RIGHT[row].to_string(),
+			Some(col) => kanata_key(MAIN[row].chars().nth(col).unwrap())
+		};
+		res.push(name);
+	}
+
+	let thumbs: &[&str] = match shape.thumbs {
+		2 => &["spc", "ralt"],
+		4 => &["lalt", "spc", "ralt", "rctl"],
+		_ => &[]
+	};
+	res.extend(thumbs.iter().map(|t| t.to_string()));
+	res
+}
+
+/// Lines of keys where every column is as wide as its widest key across all `blocks`, so the
+/// rows line up.
+fn aligned(blocks: &[&[Vec<String>]], indent: &str) -> Vec<String> {
+	let mut widths = Vec::<usize>::new();
+	for row in blocks.iter().copied().flatten() {
+		for (col, key) in row.iter().enumerate() {
+			match widths.get_mut(col) {
+				Some(width) => *width = (*width).max(key.chars().count()),
+				None => widths.push(key.chars().count())
+			}
+		}
+	}
+
+	blocks.iter()
+		.map(|rows| {
+			rows.iter()
+				.map(|row| {
+					let keys = row.iter().zip(&widths)
+						.map(|(k, width)| format!("{k:<width$}"))
+						.collect::<Vec<_>>();
+					format!("{indent}{}", keys.join(" ").trim_end())
+				})
+				.collect::<Vec<_>>()
+				.join("\n")
+		})
+		.collect()
+}
+
+fn geometry_rows<F>(layout: &FastLayout, geometry: Option<&Geometry>, key: F) -> Result<Vec<Vec<String>>>
+	where F: Fn(Option<usize>) -> String {
+	let geometry = geometry.cloned().unwrap_or_else(|| Geometry::from_shape(layout.shape));
+
+	if geometry.rows.iter().all(|row| row.is_empty()) {
+		anyhow::bail!("the geometry doesn't have any keys")
+	}
+	if let Some(i) = geometry.rows.iter().flatten().flatten().find(|&&i| i >= layout.matrix.len()) {
+		anyhow::bail!("the geometry uses position {i}, but a {} layout only has {}", layout.shape, layout.matrix.len())
+	}
+	Ok(geometry.rows.iter().map(|row| row.iter().map(|&i| key(i)).collect()).collect())
+}
+
+/// The `keymaps` array of a QMK `keymap.c`, with a layer for the base layer and each extra layer.
+/// Without a geometry, keys are passed to `LAYOUT` in the order of the layout.
+pub fn qmk_keymap(layout: &FastLayout, geometry: Option<&Geometry>) -> Result<String> {
+	let layout_macro = geometry.map(|g| g.layout_macro.as_str()).unwrap_or("LAYOUT");
+	let mut layers = Vec::new();
+
+	for actions in layer_actions(layout)? {
+		let mut rows = geometry_rows(layout, geometry, |pos| match pos.map(|pos| actions[pos]) {
+			Some(Action::Key(key, false)) => qmk_key(key),
+			Some(Action::Key(key, true)) => format!("S({})", qmk_key(key)),
+			Some(Action::Layer(layer)) => format!("MO({layer})"),
+			Some(Action::Transparent) => "KC_TRNS".into(),
+			None => "KC_NO".into()
+		})?;
+
+		// every key but the last one is followed by a comma
+		let key_count = rows.iter().map(|r| r.len()).sum::<usize>();
+		for key in rows.iter_mut().flatten().take(key_count - 1) {
+			key.push(',');
+		}
+		layers.push(rows);
+	}
+
+	let layers = layers.iter().map(|rows| rows.as_slice()).collect::<Vec<_>>();
+	let res = aligned(&layers, "        ").into_iter()
+		.zip(layer_names(layout))
+		.enumerate()
+		.map(|(i, (rows, name))| format!("    // {name}\n    [{i}] = {layout_macro}(\n{rows}\n    )"))
+		.collect::<Vec<_>>();
+
+	Ok(format!(
+		"const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {{\n{}\n}};\n",
+		res.join(",\n")
+	))
+}
+
+/// A ZMK `.keymap` with a binding block for the base layer and each extra layer.
+pub fn zmk_keymap(layout: &FastLayout, geometry: Option<&Geometry>) -> Result<String> {
+	let mut layers = Vec::new();
+
+	for actions in layer_actions(layout)? {
+		layers.push(geometry_rows(layout, geometry, |pos| match pos.map(|pos| actions[pos]) {
+			Some(Action::Key(key, false)) => format!("&kp {}", zmk_key(key)),
+			Some(Action::Key(key, true)) => format!("&kp LS({})", zmk_key(key)),
+			Some(Action::Layer(layer)) => format!("&mo {layer}"),
+			Some(Action::Transparent) => "&trans".into(),
+			None => "&none".into()
+		})?);
+	}
+
+	let layers = layers.iter().map(|rows| rows.as_slice()).collect::<Vec<_>>();
+	let res = aligned(&layers, "                ").into_iter()
+		.zip(layer_names(layout))
+		.map(|(rows, name)| format!(
+			"        {name}_layer {{\n            bindings = <\n{rows}\n            >;\n        }};"
+		))
+		.collect::<Vec<_>>();
+
+	Ok(format!(
+		"#include <behaviors.dtsi>\n#include <dt-bindings/zmk/keys.h>\n\n\
+		/ {{\n    keymap {{\n        compatible = \"zmk,keymap\";\n\n{}\n    }};\n}};\n",
+		res.join("\n\n")
+	))
+}
+
+/// A kanata `defsrc` and a `deflayer` for the base layer and each extra layer. Kanata remaps a
+/// regular keyboard, so the layout goes on the keys it covers on a US keyboard.
+pub fn kanata_config(layout: &FastLayout) -> Result<String> {
+	let names = layer_names(layout);
+	// the thumb keys end up on a row of their own, as the grid is made of full rows
+	let to_rows = |keys: &[String]| keys.chunks(layout.shape.cols)
+		.map(|row| row.to_vec())
+		.collect::<Vec<_>>();
+
+	let mut headers = vec!["defsrc".to_string()];
+	let mut blocks = vec![to_rows(&kanata_src(layout.shape))];
+
+	for (actions, name) in layer_actions(layout)?.into_iter().zip(&names) {
+		let keys = actions.into_iter()
+			.map(|action| match action {
+				Action::Key(key, false) => kanata_key(key),
+				Action::Key(key, true) => format!("S-{}", kanata_key(key)),
+				Action::Layer(layer) => format!("(layer-while-held {})", names[layer]),
+				Action::Transparent => "_".into()
+			})
+			.collect::<Vec<_>>();
+		headers.push(format!("deflayer {name}"));
+		blocks.push(to_rows(&keys));
+	}
+
+	let blocks = blocks.iter().map(|rows| rows.as_slice()).collect::<Vec<_>>();
+	let res = aligned(&blocks, "  ").into_iter()
+		.zip(headers)
+		.map(|(rows, header)| format!("({header}\n{rows}\n)"))
+		.collect::<Vec<_>>();
+
+	Ok(res.join("\n\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::tests::{golden, layered_qwerty};
+	use crate::utility::LayoutShape;
+
+	#[test]
+	fn qmk() {
+		golden("qwerty_sym.keymap.c", &qmk_keymap(&layered_qwerty(), None).unwrap());
+
+		let shape = LayoutShape::default().with_thumbs(2);
+		let thumbs = FastLayout::from((
+			"qwertyuiopasdfghjkl;zxcvbnm,./ E".chars().collect(), shape, shape.default_fingering()
+		));
+		// a split 3x5+2 in the order of its matrix, right hand rows reversed
+		let geometry = Geometry::new("LAYOUT_split", vec![
+			vec![Some(0), Some(1), Some(2), Some(3), Some(4), Some(9), Some(8), Some(7), Some(6), Some(5)],
+			vec![Some(10), Some(11), Some(12), Some(13), Some(14), Some(19), Some(18), Some(17), Some(16), Some(15)],
+			vec![Some(20), Some(21), Some(22), Some(23), Some(24), Some(29), Some(28), Some(27), Some(26), Some(25)],
+			vec![None, Some(30), Some(31), None]
+		]);
+		golden("split_thumbs.keymap.c", &qmk_keymap(&thumbs, Some(&geometry)).unwrap());
+
+		let wrong = Geometry::new("LAYOUT", vec![vec![Some(32)]]);
+		assert!(qmk_keymap(&thumbs, Some(&wrong)).is_err());
+		let empty = Geometry::new("LAYOUT", vec![vec![]]);
+		assert!(qmk_keymap(&thumbs, Some(&empty)).is_err());
+	}
+
+	#[test]
+	fn zmk() {
+		golden("qwerty_sym.keymap", &zmk_keymap(&layered_qwerty(), None).unwrap());
+	}
+
+	#[test]
+	fn kanata() {
+		golden("qwerty_sym.kbd", &kanata_config(&layered_qwerty()).unwrap());
+
+		let big = FastLayout::try_from("1234567890-qwertyuiop[asdfghjkl;'zxcvbnm,./\\").unwrap();
+		let config = kanata_config(&big).unwrap();
+		assert!(config.starts_with("(defsrc\n  1 2 3 4 5 6 7 8 9 0 -"));
+		assert!(config.contains("rsft"));
+		assert!(!config.contains("caps"));
+	}
+}
@@ -0,0 +1,453 @@
+use anyhow::Result;
+
+use super::*;
+use crate::keyboard::Keyboard;
+
+/// A key of a US keyboard that types characters, with what it types on qwerty and its codes.
+pub(crate) struct PhysicalKey {
+	pub(crate) qwerty: [char; 2],
+	pub(crate) xkb: &'static str,
+	pub(crate) scancode: u8,
+	vk: &'static str,
+	mac: u8
+}
+
+const fn key(qwerty: [char; 2], xkb: &'static str, scancode: u8, vk: &'static str, mac: u8) -> PhysicalKey {
+	PhysicalKey { qwerty, xkb, scancode, vk, mac }
+}
+
+#[rustfmt::skip]
+pub(crate) const PHYSICAL_KEYS: [PhysicalKey; 49] = [
+	key(['`', '~'], "TLDE", 0x29, "OEM_3", 50),
+	key(['1', '!'], "AE01", 0x02, "1", 18),
+	key(['2', '@'], "AE02", 0x03, "2", 19),
+	key(['3', '#'], "AE03", 0x04, "3", 20),
+	key(['4', '$'], "AE04", 0x05, "4", 21),
+	key(['5', '%'], "AE05", 0x06, "5", 23),
+	key(['6', '^'], "AE06", 0x07, "6", 22),
+	key(['7', '&'], "AE07", 0x08, "7", 26),
+	key(['8', '*'], "AE08", 0x09, "8", 28),
+	key(['9', '('], "AE09", 0x0a, "9", 25),
+	key(['0', ')'], "AE10", 0x0b, "0", 29),
+	key(['-', '_'], "AE11", 0x0c, "OEM_MINUS", 27),
+	key(['=', '+'], "AE12", 0x0d, "OEM_PLUS", 24),
+	key(['q', 'Q'], "AD01", 0x10, "Q", 12),
+	key(['w', 'W'], "AD02", 0x11, "W", 13),
+	key(['e', 'E'], "AD03", 0x12, "E", 14),
+	key(['r', 'R'], "AD04", 0x13, "R", 15),
+	key(['t', 'T'], "AD05", 0x14, "T", 17),
+	key(['y', 'Y'], "AD06", 0x15, "Y", 16),
+	key(['u', 'U'], "AD07", 0x16, "U", 32),
+	key(['i', 'I'], "AD08", 0x17, "I", 34),
+	key(['o', 'O'], "AD09", 0x18, "O", 31),
+	key(['p', 'P'], "AD10", 0x19, "P", 35),
+	key(['[', '{'], "AD11", 0x1a, "OEM_4", 33),
+	key([']', '}'], "AD12", 0x1b, "OEM_6", 30),
+	key(['\\', '|'], "BKSL", 0x2b, "OEM_5", 42),
+	key(['a', 'A'], "AC01", 0x1e, "A", 0),
+	key(['s', 'S'], "AC02", 0x1f, "S", 1),
+	key(['d', 'D'], "AC03", 0x20, "D", 2),
+	key(['f', 'F'], "AC04", 0x21, "F", 3),
+	key(['g', 'G'], "AC05", 0x22, "G", 5),
+	key(['h', 'H'], "AC06", 0x23, "H", 4),
+	key(['j', 'J'], "AC07", 0x24, "J", 38),
+	key(['k', 'K'], "AC08", 0x25, "K", 40),
+	key(['l', 'L'], "AC09", 0x26, "L", 37),
+	key([';', ':'], "AC10", 0x27, "OEM_1", 41),
+	key(['\'', '"'], "AC11", 0x28, "OEM_7", 39),
+	key(['<', '>'], "LSGT", 0x56, "OEM_102", 10),
+	key(['z', 'Z'], "AB01", 0x2c, "Z", 6),
+	key(['x', 'X'], "AB02", 0x2d, "X", 7),
+	key(['c', 'C'], "AB03", 0x2e, "C", 8),
+	key(['v', 'V'], "AB04", 0x2f, "V", 9),
+	key(['b', 'B'], "AB05", 0x30, "B", 11),
+	key(['n', 'N'], "AB06", 0x31, "N", 45),
+	key(['m', 'M'], "AB07", 0x32, "M", 46),
+	key([',', '<'], "AB08", 0x33, "OEM_COMMA", 43),
+	key(['.', '>'], "AB09", 0x34, "OEM_PERIOD", 47),
+	key(['/', '?'], "AB10", 0x35, "OEM_2", 44),
+	key([' ', ' '], "SPCE", 0x39, "SPACE", 49),
+];
+
+/// Keys left of the alpha block that don't type characters on qwerty, which the left outer pinky
+/// column puts characters on the way kanata and firmware keymaps do.
+#[rustfmt::skip]
+const OUTER_KEYS: [PhysicalKey; 3] = [
+	key(['\t', '\t'], "TAB", 0x0f, "TAB", 48),
+	key(['\0', '\0'], "CAPS", 0x3a, "CAPITAL", 57),
+	key(['\0', '\0'], "LFSH", 0x2a, "LSHIFT", 56),
+];
+
+/// The ISO key between left shift and z, which ANSI keyboards don't have.
+const ISO_KEY: &str = "LSGT";
+
+fn physical_key(qwerty: char) -> &'static PhysicalKey {
+	PHYSICAL_KEYS.iter().find(|k| k.qwerty[0] == qwerty).unwrap()
+}
+
+fn named_key(xkb: &str) -> &'static PhysicalKey {
+	PHYSICAL_KEYS.iter().chain(&OUTER_KEYS).find(|k| k.xkb == xkb).unwrap()
+}
+
+/// The physical key under every position of the shape, if there's one that types characters. Outer
+/// pinky columns are grave, tab, caps lock and left shift on the left and minus, the left bracket,
+/// quote and backslash on the right, like `kanata_src`. On ISO keyboards the left half of the
+/// bottom row is one key further left, from the key next to left shift on, like the angle mod of
+/// `iso angle`. The innermost left thumb key is the space bar.
+fn position_keys(shape: LayoutShape, keyboard: &Keyboard) -> Vec<Option<&'static PhysicalKey>> {
+	const MAIN: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl;", "zxcvbnm,./"];
+	const LEFT: [&str; 4] = ["TLDE", "TAB", "CAPS", "LFSH"];
+	const RIGHT: [&str; 4] = ["AE11", "AD11", "AC11", "BKSL"];
+	const ISO_BOTTOM: &str = "<zxcvnm,./";
+
+	let iso = keyboard.iso;
+	let mut res = Vec::new();
+
+	for i in 0..shape.grid_len() {
+		let (col, row) = (i % shape.cols, i / shape.cols + 1 - shape.row_offset());
+		let physical = match col.checked_sub(shape.col_offset()) {
+			None => named_key(LEFT[row]),
+			Some(10) => named_key(RIGHT[row]),
+			Some(col) if row == 3 && iso => physical_key(ISO_BOTTOM.chars().nth(col).unwrap()),
+			Some(col) => physical_key(MAIN[row].chars().nth(col).unwrap())
+		};
+		res.push(Some(physical));
+	}
+	for t in 0..shape.thumbs {
+		res.push((t + 1 == shape.thumbs / 2).then(|| physical_key(' ')));
+	}
+	res
+}
+
+/// The shifted version of a character, using the same rules as `Translator::letter_to_lowercase`
+/// for letters, and the US shifted symbols for the rest of what an ANSI keyboard types.
+fn shifted(c: char) -> char {
+	let mut upper = c.to_uppercase();
+	if upper.clone().count() == 1 && c.is_alphabetic() {
+		return upper.next().unwrap()
+	}
+	PHYSICAL_KEYS.iter()
+		.find(|k| k.qwerty[0] == c && k.xkb != ISO_KEY && !c.is_alphabetic())
+		.map(|k| k.qwerty[1])
+		.unwrap_or(c)
+}
+
+/// Unshifted and shifted characters of every physical key. Keys the layout doesn't cover keep
+/// what they type on qwerty, and those of `OUTER_KEYS` are only there when the layout covers them.
+/// A layer named `shift` sets the shifted characters explicitly.
+fn os_keys(layout: &FastLayout, keyboard: &Keyboard) -> Result<Vec<(&'static PhysicalKey, [char; 2])>> {
+	let shift_layer = layout.layers.iter().find(|l| l.name == "shift");
+	let mut res = PHYSICAL_KEYS.iter()
+		.filter(|k| keyboard.iso || k.xkb != ISO_KEY)
+		.map(|k| (k, k.qwerty))
+		.collect::<Vec<_>>();
+	let mut unmapped = Vec::new();
+
+	for (i, physical) in position_keys(layout.shape, keyboard).into_iter().enumerate() {
+		let c = layout.matrix[i];
+		let Some(physical) = physical else {
+			unmapped.push(c);
+			continue
+		};
+		let shifted = match shift_layer.map(|l| l.matrix[i]) {
+			Some(s) if s != ' ' => s,
+			_ => shifted(c)
+		};
+		match res.iter_mut().find(|(k, _)| std::ptr::eq(*k, physical)) {
+			Some(key) => key.1 = [c, shifted],
+			None => res.push((physical, [c, shifted]))
+		}
+	}
+
+	if !unmapped.is_empty() {
+		anyhow::bail!(
+			"{} are on thumb keys, of which only the space bar types characters on a regular keyboard",
+			unmapped.iter().map(|c| format!("'{c}'")).collect::<Vec<_>>().join(", ")
+		)
+	}
+	Ok(res)
+}
+
+/// Names of the xkb keysyms for the ASCII symbols.
+pub(crate) const KEYSYM_NAMES: [(char, &str); 33] = [
+	(' ', "space"), ('!', "exclam"), ('"', "quotedbl"), ('#', "numbersign"), ('$', "dollar"),
+	('%', "percent"), ('&', "ampersand"), ('\'', "apostrophe"), ('(', "parenleft"),
+	(')', "parenright"), ('*', "asterisk"), ('+', "plus"), (',', "comma"), ('-', "minus"),
+	('.', "period"), ('/', "slash"), (':', "colon"), (';', "semicolon"), ('<', "less"),
+	('=', "equal"), ('>', "greater"), ('?', "question"), ('@', "at"), ('[', "bracketleft"),
+	('\\', "backslash"), (']', "bracketright"), ('^', "asciicircum"), ('_', "underscore"),
+	('`', "grave"), ('{', "braceleft"), ('|', "bar"), ('}', "braceright"), ('~', "asciitilde")
+];
+
+fn xkb_keysym(c: char) -> String {
+	if c.is_ascii_alphanumeric() {
+		c.to_string()
+	} else if let Some((_, name)) = KEYSYM_NAMES.iter().find(|(n, _)| *n == c) {
+		name.to_string()
+	} else {
+		format!("U{:04X}", c as u32)
+	}
+}
+
+/// An xkb `symbols` file that changes the keys of the layout on top of `us(basic)`.
+pub fn xkb_symbols(layout: &FastLayout, keyboard: &Keyboard, name: &str) -> Result<String> {
+	let keys = os_keys(layout, keyboard)?
+		.into_iter()
+		.filter(|(k, chars)| k.qwerty != *chars)
+		.map(|(k, [c, s])| format!("    key <{}> {{ [ {}, {} ] }};", k.xkb, xkb_keysym(c), xkb_keysym(s)))
+		.collect::<Vec<_>>();
+
+	Ok(format!(
+		"default partial alphanumeric_keys\nxkb_symbols \"basic\" {{\n    include \"us(basic)\"\n    \
+		name[Group1] = \"{name}\";\n\n{}\n}};\n",
+		keys.join("\n")
+	))
+}
+
+fn klc_char(c: char) -> Result<String> {
+	if c.is_ascii_alphanumeric() {
+		Ok(c.to_string())
+	} else if (c as u32) <= 0xFFFF {
+		Ok(format!("{:04x}", c as u32))
+	} else {
+		anyhow::bail!("'{c}' is outside of what .klc files can describe")
+	}
+}
+
+/// The virtual key US keyboards type a character with unshifted, which shortcuts like ctrl+comma
+/// are bound to. '<' only has one on ISO keyboards, on the ISO key.
+fn conventional_vk(c: char, iso: bool) -> Option<String> {
+	const OEM_CHARS: [(char, &str); 12] = [
+		(';', "OEM_1"), ('/', "OEM_2"), ('`', "OEM_3"), ('[', "OEM_4"), ('\\', "OEM_5"), (']', "OEM_6"),
+		('\'', "OEM_7"), ('=', "OEM_PLUS"), (',', "OEM_COMMA"), ('-', "OEM_MINUS"), ('.', "OEM_PERIOD"),
+		('<', "OEM_102")
+	];
+	if c.is_ascii_alphanumeric() {
+		return Some(c.to_ascii_uppercase().to_string())
+	}
+	OEM_CHARS.iter()
+		.find(|&&(oem, vk)| oem == c && (iso || vk != "OEM_102"))
+		.map(|(_, vk)| vk.to_string())
+}
+
+/// The source of a Windows layout for the Microsoft Keyboard Layout Creator. Keys get the virtual
+/// key of the character they type, see `conventional_vk`. Keys typing other characters, or one that
+/// another key types already, get a virtual key no other key uses: their own on qwerty if it's
+/// free, then an OEM one, then the one of a digit or letter the layout leaves unused.
+pub fn klc_layout(layout: &FastLayout, keyboard: &Keyboard, name: &str) -> Result<String> {
+	const OEM_KEYS: [&str; 13] = [
+		"OEM_1", "OEM_2", "OEM_3", "OEM_4", "OEM_5", "OEM_6", "OEM_7", "OEM_8", "OEM_PLUS",
+		"OEM_COMMA", "OEM_MINUS", "OEM_PERIOD", "OEM_102"
+	];
+	let keys = os_keys(layout, keyboard)?;
+	let mut used = Vec::new();
+
+	let mut vks = Vec::with_capacity(keys.len());
+	for (k, [c, _]) in &keys {
+		let vk = if k.xkb == "SPCE" { Some(k.vk.to_string()) } else { conventional_vk(*c, keyboard.iso) };
+		let vk = vk.filter(|vk| !used.contains(vk));
+		used.extend(vk.clone());
+		vks.push(vk);
+	}
+
+	let character_vks = OEM_KEYS.into_iter()
+		.filter(|vk| keyboard.iso || *vk != "OEM_102")
+		.map(str::to_string)
+		.chain(('0'..='9').chain('A'..='Z').map(String::from))
+		.collect::<Vec<_>>();
+
+	let mut lines = Vec::new();
+	for ((k, [c, s]), vk) in keys.into_iter().zip(vks) {
+		let vk = match vk {
+			Some(vk) => vk,
+			None => {
+				// keys that don't type characters on qwerty only keep their own virtual key when no
+				// other one is left, as it makes them work like a tab, caps lock or shift key
+				let own = k.vk.to_string();
+				let free = std::iter::once(&own)
+					.filter(|vk| character_vks.contains(vk))
+					.chain(&character_vks)
+					.chain(std::iter::once(&own))
+					.find(|vk| !used.contains(vk))
+					.ok_or_else(|| anyhow::anyhow!("there's no virtual key left for '{c}'"))?
+					.clone();
+				used.push(free.clone());
+				free
+			}
+		};
+		let caps = if c != s && c.is_alphabetic() { 1 } else { 0 };
+		lines.push(format!("{:02x}\t{vk}\t\t{caps}\t{}\t{}", k.scancode, klc_char(c)?, klc_char(s)?));
+	}
+
+	let id = name.chars().filter(char::is_ascii_alphanumeric).take(8).collect::<String>().to_lowercase();
+	Ok(format!(
+		"KBD\t{id}\t\"{name}\"\n\nCOPYRIGHT\t\"\"\n\nCOMPANY\t\"\"\n\nLOCALENAME\t\"en-US\"\n\n\
+		LOCALEID\t\"00000409\"\n\nVERSION\t1.0\n\nSHIFTSTATE\n\n0\t//Column 4\n1\t//Column 5 : Shft\n\n\
+		LAYOUT\n\n//SC\tVK_\t\tCap\t0\t1\n//--\t----\t\t----\t----\t----\n\n{}\n\n\
+		DESCRIPTIONS\n\n0409\t{name}\n\nLANGUAGENAMES\n\n0409\tEnglish (United States)\n\nENDKBD\n",
+		lines.join("\n")
+	))
+}
+
+fn xml_escape(c: char) -> String {
+	match c {
+		'&' | '<' | '>' | '"' | '\'' => format!("&#x{:04X};", c as u32),
+		_ => c.to_string()
+	}
+}
+
+/// A macOS `.keylayout` with an unshifted and a shifted key map. Caps lock and shift can't type
+/// characters on macOS, so layouts with characters on them fail.
+pub fn keylayout(layout: &FastLayout, keyboard: &Keyboard, name: &str) -> Result<String> {
+	// return, tab, delete and escape, which every key map needs to be usable
+	const CONTROL_KEYS: [(u8, &str); 4] = [(36, "&#x000D;"), (48, "&#x0009;"), (51, "&#x0008;"), (53, "&#x001B;")];
+
+	let keys = os_keys(layout, keyboard)?;
+	if let Some((k, [c, _])) = keys.iter().find(|(k, _)| ["CAPS", "LFSH"].contains(&k.xkb)) {
+		anyhow::bail!("'{c}' is on {}, which macOS doesn't let type characters", if k.xkb == "CAPS" { "caps lock" } else { "shift" })
+	}
+	let id = -(name.bytes().map(|b| b as i32).sum::<i32>() % 30000 + 1);
+
+	let key_map = |index: usize| {
+		let mut res = keys.iter()
+			.map(|(k, chars)| (k.mac, xml_escape(chars[index])))
+			.chain(CONTROL_KEYS.iter().map(|&(code, out)| (code, out.to_string())))
+			.collect::<Vec<_>>();
+		res.sort_by_key(|(code, _)| *code);
+
+		let keys = res.into_iter()
+			.map(|(code, output)| format!("\t\t\t<key code=\"{code}\" output=\"{output}\"/>"))
+			.collect::<Vec<_>>();
+		format!("\t\t<keyMap index=\"{index}\">\n{}\n\t\t</keyMap>", keys.join("\n"))
+	};
+
+	Ok(format!(
+		"<?xml version=\"1.1\" encoding=\"UTF-8\"?>\n\
+		<!DOCTYPE keyboard SYSTEM \"file://localhost/System/Library/DTDs/KeyboardLayout.dtd\">\n\
+		<keyboard group=\"126\" id=\"{id}\" name=\"{}\" maxout=\"1\">\n\
+		\t<layouts>\n\t\t<layout first=\"0\" last=\"17\" mapSet=\"ANSI\" modifiers=\"Modifiers\"/>\n\t</layouts>\n\
+		\t<modifierMap id=\"Modifiers\" defaultIndex=\"0\">\n\
+		\t\t<keyMapSelect mapIndex=\"0\">\n\t\t\t<modifier keys=\"\"/>\n\t\t</keyMapSelect>\n\
+		\t\t<keyMapSelect mapIndex=\"1\">\n\t\t\t<modifier keys=\"anyShift caps?\"/>\n\t\t</keyMapSelect>\n\
+		\t</modifierMap>\n\
+		\t<keyMapSet id=\"ANSI\">\n{}\n{}\n\t</keyMapSet>\n</keyboard>\n",
+		name.chars().map(xml_escape).collect::<String>(), key_map(0), key_map(1)
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use itertools::Itertools;
+
+	use super::*;
+	use super::super::tests::golden;
+	use crate::layout::layers_from_kb;
+
+	fn dvorak() -> FastLayout {
+		FastLayout::try_from("',.pyfgcrlaoeuidhtns;qjkxbmwvz").unwrap()
+	}
+
+	#[test]
+	fn shift_rules() {
+		assert_eq!(shifted('a'), 'A');
+		assert_eq!(shifted('ß'), 'ß');
+		assert_eq!(shifted('é'), 'É');
+		assert_eq!(shifted(','), '<');
+		assert_eq!(shifted('7'), '&');
+		assert_eq!(shifted('<'), '<');
+	}
+
+	#[test]
+	fn physical_keys() {
+		let keys = os_keys(&dvorak(), &Keyboard::default()).unwrap();
+		assert_eq!(keys.len(), PHYSICAL_KEYS.len() - 1);
+		let q = keys.iter().find(|(k, _)| k.xkb == "AD01").unwrap();
+		assert_eq!(q.1, ['\'', '"']);
+
+		let wide = FastLayout::try_from("qwertyuiop[]asdfghjkl;'\\zxcvbnm,./=-").unwrap();
+		let keys = os_keys(&wide, &Keyboard::default()).unwrap();
+		assert_eq!(keys.len(), PHYSICAL_KEYS.len() - 1 + OUTER_KEYS.len());
+		let tab = keys.iter().find(|(k, _)| k.xkb == "TAB").unwrap();
+		assert_eq!(tab.1, ['q', 'Q']);
+
+		let names = |keys: Vec<Option<&PhysicalKey>>| keys.into_iter().map(|k| k.map(|k| k.xkb)).collect::<Vec<_>>();
+		let shape = LayoutShape::new(3, 12);
+		let iso = names(position_keys(shape, &Keyboard::load("iso angle").unwrap()));
+		assert_eq!(iso[..12], [Some("TAB"), Some("AD01"), Some("AD02"), Some("AD03"), Some("AD04"), Some("AD05"),
+			Some("AD06"), Some("AD07"), Some("AD08"), Some("AD09"), Some("AD10"), Some("AD11")]);
+		assert_eq!(iso[24..30], [Some("LFSH"), Some("LSGT"), Some("AB01"), Some("AB02"), Some("AB03"), Some("AB04")]);
+		assert_eq!(iso[30], Some("AB06"));
+		assert_eq!(iso[35], Some("BKSL"));
+		assert_eq!(names(position_keys(shape, &Keyboard::default()))[25], Some("AB01"));
+
+		let shape = LayoutShape::new(3, 10).with_thumbs(2);
+		let thumbs = FastLayout::from(("qwertyuiopasdfghjkl;zxcvbnm,./ -".chars().collect(), shape, shape.default_fingering()));
+		assert!(os_keys(&thumbs, &Keyboard::default()).is_err());
+
+		let layers = layers_from_kb(
+			"layer shift ^\n_ _ _ _ _  _ _ _ _ _\n_ _ _ _ _  _ _ _ _ _\n_ _ _ _ _  _ _ _ _ !", dvorak().shape
+		).unwrap();
+		let keys = os_keys(&dvorak().with_layers(layers), &Keyboard::default()).unwrap();
+		let z = keys.iter().find(|(k, _)| k.xkb == "AB10").unwrap();
+		assert_eq!(z.1, ['z', '!']);
+	}
+
+	#[test]
+	fn xkb() {
+		golden("dvorak.xkb", &xkb_symbols(&dvorak(), &Keyboard::default(), "Dvorak").unwrap());
+	}
+
+	#[test]
+	fn klc() {
+		golden("dvorak.klc", &klc_layout(&dvorak(), &Keyboard::default(), "Dvorak").unwrap());
+
+		assert_eq!(conventional_vk(',', false).as_deref(), Some("OEM_COMMA"));
+		assert_eq!(conventional_vk('<', false), None);
+		assert_eq!(conventional_vk('<', true).as_deref(), Some("OEM_102"));
+		assert_eq!(conventional_vk('é', true), None);
+
+		// the ISO key is on the layout, so it gets ';' and its virtual key
+		let iso = klc_layout(&dvorak(), &Keyboard::load("iso angle").unwrap(), "Dvorak").unwrap();
+		assert!(iso.contains("\n56\tOEM_1\t"));
+		// with the 7 symbols qwerty keeps, as many as there are OEM keys without the ISO key
+		let symbols = FastLayout::try_from("é<>!?fgcrlaoeuidhtnspqjkxbmwvz").unwrap();
+		assert!(!klc_layout(&symbols, &Keyboard::default(), "Symbols").unwrap().contains("OEM_102"));
+	}
+
+	#[test]
+	fn shuffled_number_row() {
+		// the keys qwerty keeps type the symbols on the number row a second time, using up the OEM
+		// virtual keys
+		let layout = FastLayout::try_from("[]'-=\\`/;,qwfpbjluy.arstgmneiozxcdvkh123").unwrap();
+		let klc = klc_layout(&layout, &Keyboard::default(), "Shuffled").unwrap();
+		let vks = klc.lines()
+			.filter(|l| l.len() > 2 && l.as_bytes()[2] == b'\t')
+			.map(|l| l.split('\t').nth(1).unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(vks.len(), PHYSICAL_KEYS.len() - 1);
+		assert!(vks.iter().all_unique());
+		assert!(vks.contains(&"4"));
+	}
+
+	#[test]
+	fn bigger_shape() {
+		let layout = FastLayout::try_from("]qwfpbjluy;[=arstgmneio'-zxcdvkh,./\\").unwrap();
+
+		let xkb = xkb_symbols(&layout, &Keyboard::default(), "Wide").unwrap();
+		assert!(xkb.contains("key <TAB> { [ bracketright, braceright ] };"));
+		assert!(xkb.contains("key <LFSH> { [ minus, underscore ] };"));
+
+		let klc = klc_layout(&layout, &Keyboard::default(), "Wide").unwrap();
+		assert!(klc.contains("\n0f\t"));
+		assert!(klc.contains("\n3a\t"));
+		assert!(klc.contains("\n2a\t"));
+
+		assert!(keylayout(&layout, &Keyboard::default(), "Wide").is_err());
+	}
+
+	#[test]
+	fn mac() {
+		golden("dvorak.keylayout", &keylayout(&dvorak(), &Keyboard::default(), "Dvorak").unwrap());
+	}
+}
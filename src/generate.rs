@@ -10,96 +10,70 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use anyhow::Result;
 
 use crate::utility::*;
-use crate::trigram_patterns::TrigramPattern;
+use crate::trigram_patterns::TrigramTaxonomy;
+use crate::constraints::Constraint;
+use crate::travel::{EffortModel, get_travel_effort_map};
 use crate::language_data::{BigramData, TrigramData, LanguageData};
 use crate::layout::*;
+use crate::import;
 use crate::weights::{Weights, Config};
+use annealing::{Annealing, Optimizer};
 
 #[cfg(test)]
 static PRUNED_COUNT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
 #[cfg(test)]
 static NOT_PRUNED: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
 
+/// How often every trigram class occurs, in the order the classes are tried, along with the
+/// trigrams no class matches and the ones with a character that isn't on the layout.
 #[derive(Clone, Default)]
 pub struct TrigramStats {
-	pub alternates: f64,
-	pub alternates_sfs: f64,
-	pub inrolls: f64,
-	pub outrolls: f64,
-	pub onehands: f64,
-	pub redirects: f64,
-	pub bad_redirects: f64,
-	pub sfbs: f64,
-	pub bad_sfbs: f64,
-	pub sfts: f64,
+	pub classes: IndexMap<String, f64>,
 	pub other: f64,
 	pub invalid: f64
 }
 
+/// Groups of the built-in trigram classes whose sum is shown after the last of them.
+const TRIGRAM_GROUPS: [(&str, &[&str]); 3] = [
+	("Total Rolls", &["Inrolls", "Outrolls"]),
+	("Total Alternates", &["Alternates", "Alternates (sfs)"]),
+	("Total Redirects", &["Redirects", "Bad Redirects"])
+];
+
+impl TrigramStats {
+	pub fn get(&self, class: &str) -> f64 {
+		self.classes.get(class).copied().unwrap_or_default()
+	}
+
+	/// How often trigrams of any of `classes` occur.
+	pub fn total(&self, classes: &[&str]) -> f64 {
+		classes.iter().map(|c| self.get(c)).sum()
+	}
+}
+
 impl std::fmt::Display for TrigramStats {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(
-			f,
-"Inrolls: {:.3}%
-Outrolls: {:.3}% 
-Total Rolls: {:.3}%
-Onehands: {:.3}%\n
-Alternates: {:.3}%
-Alternates (sfs): {:.3}%
-Total Alternates: {:.3}%\n
-Redirects: {:.3}%
-Bad Redirects: {:.3}%
-Total Redirects: {:.3}%\n
-Bad Sfbs: {:.3}%,
-Sft: {:.3}%",
-			self.inrolls*100.0,
-			self.outrolls*100.0,
-			(self.inrolls + self.outrolls)*100.0,
-			self.onehands*100.0,
-			self.alternates*100.0,
-			self.alternates_sfs*100.0,
-			(self.alternates + self.alternates_sfs)*100.0,
-			self.redirects*100.0,
-			self.bad_redirects*100.0,
-			(self.redirects + self.bad_redirects)*100.0,
-			self.bad_sfbs*100.0,
-			self.sfts*100.0
-		)
+		let mut lines = Vec::new();
+		for (i, (name, freq)) in self.classes.iter().enumerate() {
+			lines.push(format!("{name}: {:.3}%", freq * 100.0));
+
+			// a group with classes a taxonomy doesn't have isn't shown
+			for (total, group) in TRIGRAM_GROUPS {
+				let last = group.iter().map(|c| self.classes.get_index_of(*c)).max().flatten();
+				if group.contains(&name.as_str()) && last == Some(i) {
+					lines.push(format!("{total}: {:.3}%", self.total(group) * 100.0));
+				}
+			}
+		}
+		write!(f, "{}", lines.join("\n"))
 	}
 }
 
 impl std::fmt::Debug for TrigramStats {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(
-			f,
-			"Inrolls: {:.3}%\n
-			Outrolls: {:.3}%\n
-			Total Rolls: {:.3}%\n
-			Onehands: {:.3}%\n\n\
-			Alternates: {:.3}%\n
-			Alternates (sfs): {:.3}%\n
-			Total Alternates: {:.3}%\n\n
-			Redirects: {:.3}%\n\
-			Bad Redirects: {:.3}%\n
-			Total Redirects: {:.3}%\n\n
-			Bad Sfbs: {:.3}%\n
-			Sft: {:.3}%\n\n
-			Other: {:.3}%\n
-			Invalid: {:.3}%",
-			self.inrolls*100.0,
-			self.outrolls*100.0,
-			(self.inrolls + self.outrolls)*100.0,
-			self.onehands*100.0,
-			self.alternates*100.0,
-			self.alternates_sfs*100.0,
-			(self.alternates + self.alternates_sfs)*100.0,
-			self.redirects*100.0,
-			self.bad_redirects*100.0,
-			(self.redirects + self.bad_redirects)*100.0,
-			self.bad_sfbs*100.0,
-			self.sfts*100.0,
-			self.other*100.0,
-			self.invalid*100.0
+			f, "{self}\n\nOther: {:.3}%\nInvalid: {:.3}%",
+			self.other * 100.0, self.invalid * 100.0
 		)
 	}
 }
@@ -112,54 +86,319 @@ fn format_fspeed(finger_speed: &[f64]) -> String {
 	finger_speed_str.join(", ")
 }
 
+/// What typing with a repeat key saves, as a share of all bigrams.
+#[derive(Clone, Default)]
+pub struct RepeatStats {
+	pub sfbs: f64,
+	pub same_key: f64
+}
+
+impl std::fmt::Display for RepeatStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f, "Repeat key removes:\n    Sfbs: {:.3}%\n    Same key: {:.3}%",
+			self.sfbs * 100.0, self.same_key * 100.0
+		)
+	}
+}
+
+/// How much of the typing combos do, as a share of all characters, and the bigrams between a combo
+/// and a key on one of the fingers it presses, as a share of all bigrams.
+#[derive(Clone, Default)]
+pub struct ComboStats {
+	pub presses: f64,
+	pub sfbs: f64
+}
+
+impl std::fmt::Display for ComboStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f, "Combos: {:.3}%\n    Sfbs: {:.3}%",
+			self.presses * 100.0, self.sfbs * 100.0
+		)
+	}
+}
+
+/// The weighted components of a score. Penalties are negative, so they add up to the score.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScoreComponents {
+	pub effort: f64,
+	pub usage: f64,
+	pub fspeed: f64,
+	pub scissors: f64,
+	pub lsbs: f64,
+	pub trigrams: f64,
+	pub combos: f64,
+	pub constraints: f64,
+	pub hand_balance: f64,
+	pub row_use: f64
+}
+
+impl ScoreComponents {
+	pub fn total(&self) -> f64 {
+		self.effort + self.usage + self.fspeed + self.scissors + self.lsbs + self.trigrams + self.combos
+			+ self.constraints + self.hand_balance + self.row_use
+	}
+
+	fn zip_with(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+		Self {
+			effort: f(self.effort, other.effort),
+			usage: f(self.usage, other.usage),
+			fspeed: f(self.fspeed, other.fspeed),
+			scissors: f(self.scissors, other.scissors),
+			lsbs: f(self.lsbs, other.lsbs),
+			trigrams: f(self.trigrams, other.trigrams),
+			combos: f(self.combos, other.combos),
+			constraints: f(self.constraints, other.constraints),
+			hand_balance: f(self.hand_balance, other.hand_balance),
+			row_use: f(self.row_use, other.row_use)
+		}
+	}
+}
+
+impl std::ops::Add for ScoreComponents {
+	type Output = Self;
+
+	fn add(self, other: Self) -> Self {
+		self.zip_with(other, |a, b| a + b)
+	}
+}
+
+impl std::ops::Sub for ScoreComponents {
+	type Output = Self;
+
+	fn sub(self, other: Self) -> Self {
+		self.zip_with(other, |a, b| a - b)
+	}
+}
+
+impl std::fmt::Display for ScoreComponents {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f, concat!(
+				"{:+.4} (effort {:+.4}, usage {:+.4}, fspeed {:+.4}, scissors {:+.4}, lsbs {:+.4}, ",
+				"trigrams {:+.4}, combos {:+.4}, constraints {:+.4}, hand balance {:+.4}, row use {:+.4})"
+			),
+			self.total(), self.effort, self.usage, self.fspeed, self.scissors, self.lsbs, self.trigrams,
+			self.combos, self.constraints, self.hand_balance, self.row_use
+		)
+	}
+}
+
+/// A score split into its components, for the whole layout and for the character on every key.
+/// Bigrams and trigrams are split evenly between their keys, finger usage between the keys of a
+/// finger by how often they're typed, combos between the keys pressed for them, broken
+/// constraints between the keys of their characters, and hand imbalance and overused rows between
+/// the keys of the busier hand and the row by how often they're typed, so the keys add up to the
+/// total.
+#[derive(Clone, Debug, Default)]
+pub struct ScoreBreakdown {
+	pub total: ScoreComponents,
+	pub keys: Vec<(char, ScoreComponents)>
+}
+
+impl ScoreBreakdown {
+	/// What the key `c` is on contributes, `None` if it isn't on the layout.
+	pub fn char(&self, c: char) -> Option<ScoreComponents> {
+		self.keys.iter().find(|(k, _)| *k == c).map(|(_, components)| *components)
+	}
+}
+
+impl std::fmt::Display for ScoreBreakdown {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Total: {}", self.total)?;
+		for (c, components) in &self.keys {
+			write!(f, "\n    {c}: {components}")?;
+		}
+		Ok(())
+	}
+}
+
+/// An n-gram along with its frequency and the share of its metric it makes up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Offender {
+	pub ngram: String,
+	pub freq: f64,
+	pub share: f64
+}
+
+impl std::fmt::Display for Offender {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {:.3}% ({:.1}%)", self.ngram, self.freq * 100.0, self.share * 100.0)
+	}
+}
+
+/// The n-grams that make up the most of every metric of a layout, most common first.
+#[derive(Clone, Default)]
+pub struct Offenders {
+	pub sfbs: Vec<Offender>,
+	pub dsfbs: Vec<Offender>,
+	pub scissors: Vec<Offender>,
+	pub lsbs: Vec<Offender>,
+	/// The most common trigrams of every trigram class.
+	pub trigrams: IndexMap<String, Vec<Offender>>
+}
+
+impl std::fmt::Display for Offenders {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let section = |name: &str, offenders: &[Offender]| {
+			let lines = offenders.iter().map(|o| format!("\n    {o}")).collect::<String>();
+			format!("{name}:{lines}")
+		};
+
+		let mut sections = vec![
+			section("Sfbs", &self.sfbs), section("Dsfbs", &self.dsfbs), section("Scissors", &self.scissors),
+			section("Lsbs", &self.lsbs)
+		];
+		sections.extend(self.trigrams.iter().map(|(class, offenders)| section(class, offenders)));
+		write!(f, "{}", sections.join("\n\n"))
+	}
+}
+
+/// The `n` most common of `ngrams`, with their share of the frequency of all of them.
+fn top_offenders(mut ngrams: Vec<(String, f64)>, n: usize) -> Vec<Offender> {
+	let total = ngrams.iter().map(|(_, freq)| freq).sum::<f64>();
+	ngrams.sort_by(|(_, f1), (_, f2)| f2.partial_cmp(f1).unwrap());
+
+	ngrams.into_iter()
+		.take(n)
+		.map(|(ngram, freq)| Offender { ngram, freq, share: if total > 0.0 { freq / total } else { 0.0 } })
+		.collect()
+}
+
+/// How much a row is typed on with either hand, as a share of all key presses.
+#[derive(Clone, Copy, Default)]
+pub struct RowUsage {
+	pub left: f64,
+	pub right: f64
+}
+
+impl RowUsage {
+	pub fn total(&self) -> f64 {
+		self.left + self.right
+	}
+}
+
+impl std::fmt::Display for RowUsage {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f, "{:.3}% ({:.3}% left, {:.3}% right)",
+			self.total() * 100.0, self.left * 100.0, self.right * 100.0
+		)
+	}
+}
+
+/// Usage of the rows of the alpha block, and the bigrams that jump between its top and bottom row
+/// on one hand, as a share of all bigrams.
+#[derive(Clone, Default)]
+pub struct RowStats {
+	pub top: RowUsage,
+	pub home: RowUsage,
+	pub bottom: RowUsage,
+	pub row_jumps: f64
+}
+
+impl std::fmt::Display for RowStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f, "Top row:    {}\nHome row:   {}\nBottom row: {}\nRow jumps: {:.3}%",
+			self.top, self.home, self.bottom, self.row_jumps * 100.0
+		)
+	}
+}
+
 #[derive(Clone)]
 pub struct LayoutStats {
 	pub sfb: f64,
 	pub dsfb: f64,
 	pub dsfb2: f64,
 	pub dsfb3: f64,
+	/// Dsfbs of every skip distance, weighted by how much they count compared to sfbs.
+	pub weighted_dsfb: f64,
 	pub scissors: f64,
+	/// Lateral stretch bigrams, as a share of all bigrams.
+	pub lsbs: f64,
+	/// Share of the keys typed with the left hand.
+	pub hand_balance: f64,
+	pub rows: RowStats,
 	pub trigram_stats: TrigramStats,
 	pub fspeed: f64,
-	pub finger_speed: [f64; 8]
+	pub finger_speed: [f64; FINGER_COUNT],
+	pub repeat: Option<RepeatStats>,
+	pub combos: Option<ComboStats>,
+	pub broken_constraints: Vec<Constraint>
 }
 
 impl std::fmt::Display for LayoutStats {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(
-			f, concat!("Sfb:  {:.3}%\nDsfb: {:.3}%\nFinger Speed: {:.3}\n",
-			"    [{}]\nScissors: {:.3}%\n\n{}"),
-			self.sfb * 100.0, self.dsfb * 100.0, self.fspeed * 10.0,
-			format_fspeed(&self.finger_speed), self.scissors * 100.0, self.trigram_stats
+			f, concat!("Sfb:  {:.3}%\nDsfb: {:.3}%\nDsfb2: {:.3}%\nDsfb3: {:.3}%\nWeighted dsfb: {:.3}%\n",
+			"Finger Speed: {:.3}\n    [{}]\nScissors: {:.3}%\nLsbs: {:.3}%\nHand balance: {:.3}% left, {:.3}% right\n\n{}\n\n{}{}{}{}"),
+			self.sfb * 100.0, self.dsfb * 100.0, self.dsfb2 * 100.0, self.dsfb3 * 100.0,
+			self.weighted_dsfb * 100.0, self.fspeed * 10.0,
+			format_fspeed(&self.finger_speed), self.scissors * 100.0, self.lsbs * 100.0,
+			self.hand_balance * 100.0, (1.0 - self.hand_balance) * 100.0, self.rows,
+			self.repeat.as_ref().map(|r| format!("{r}\n\n")).unwrap_or_default(),
+			self.combos.as_ref().map(|c| format!("{c}\n\n")).unwrap_or_default(), self.trigram_stats,
+			self.broken_constraints.iter().map(|c| format!("\n\nBroken: {c}")).collect::<String>()
 		)
 	}
 }
 
+/// One swap on the way from one layout to another, with the score and stats after making it.
+#[derive(Clone)]
+pub struct SwapStep {
+	pub swap: PosPair,
+	pub score: f64,
+	pub score_change: f64,
+	pub stats: LayoutStats
+}
+
+impl std::fmt::Display for SwapStep {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "swap {}: {:.5} ({:+.5})", self.swap, self.score, self.score_change)
+	}
+}
+
 pub type CharToFinger<T> = Map<T, usize>;
-pub type Matrix<T> = [T; 30];
+pub type Matrix<T> = Vec<T>;
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct LayoutCache {
-	effort: [f64; 30],
+	effort: Vec<f64>,
 	effort_total: f64,
 
 	scissors: f64,
+	lsbs: f64,
 
-	usage: [f64; 8],
+	usage: [f64; FINGER_COUNT],
 	usage_total: f64,
 
-	fspeed: [f64; 8],
+	fspeed: [f64; FINGER_COUNT],
 	fspeed_total: f64,
 
 	// trigrams: FxHashMap<(char, Option<char>), f64>,
 	trigrams_total: f64,
 
+	constraints: f64,
+
+	hand_usage: [f64; 2],
+	hand_balance: f64,
+
+	row_usage: [f64; 3],
+	row_use: f64,
+
+	/// The score of every combo of the layout.
+	combos: Vec<f64>,
+	combos_total: f64,
+
 	total_score: f64
 }
 
 impl LayoutCache {
 	pub fn total_score(&self) -> f64 {
-		self.trigrams_total - self.scissors - self.effort_total - self.usage_total - self.fspeed_total
+		self.trigrams_total - self.scissors - self.lsbs - self.effort_total - self.usage_total
+			- self.fspeed_total - self.constraints - self.hand_balance - self.row_use - self.combos_total
 	}
 }
 
@@ -167,16 +406,10 @@ type PerCharTrigrams = FxHashMap<[char; 2], TrigramData>;
 
 static COLS: [usize; 6] = [0, 1, 2, 7, 8, 9];
 
-pub(crate) fn pinned_swaps(pins: &[usize]) -> Vec<PosPair> {
-	let mut map = [true; 30];
-	for i in 0..30 {
-		if pins.contains(&i) {
-			map[i] = false;
-		}
-	}
+pub(crate) fn pinned_swaps(pins: &[usize], possible_swaps: &[PosPair]) -> Vec<PosPair> {
 	let mut res = Vec::new();
-	for ps in POSSIBLE_SWAPS {
-		if map[ps.0] && map[ps.1] {
+	for &ps in possible_swaps {
+		if !pins.contains(&ps.0) && !pins.contains(&ps.1) {
 			res.push(ps);
 		}
 	}
@@ -186,14 +419,39 @@ pub(crate) fn pinned_swaps(pins: &[usize]) -> Vec<PosPair> {
 pub struct LayoutGeneration {
 	pub language: String,
 	pub data: LanguageData,
-	pub chars_for_generation: [char; 30],
-
-	fspeed_vals: [(PosPair, f64); 48],
-	effort_map: [f64; 30],
-	scissor_indices: [PosPair; 28],
+	pub chars_for_generation: Vec<char>,
+	/// Key sets that generated layouts put combos on.
+	pub combos: Vec<Vec<usize>>,
+	/// The characters generated layouts can type with combos, the most common ones after
+	/// `chars_for_generation`.
+	pub chars_for_combos: Vec<char>,
+	pub shape: LayoutShape,
+	pub fingering: Fingering,
+	pub possible_swaps: Vec<PosPair>,
+	pub trigram_taxonomy: TrigramTaxonomy,
+	pub constraints: Vec<Constraint>,
+	/// How `generate` optimizes layouts.
+	pub optimizer: Optimizer,
+	pub annealing: Annealing,
+
+	fspeed_vals: Vec<(PosPair, f64)>,
+	col_ranges: [(usize, usize); FINGER_COUNT],
+	col_keys: [Vec<usize>; FINGER_COUNT],
+	effort_map: Vec<f64>,
+	scissor_indices: Vec<PosPair>,
+	affects_scissor: Vec<bool>,
+	row_jump_indices: Vec<PosPair>,
+	lsb_indices: Vec<PosPair>,
+	/// The lsb pairs every key is part of.
+	key_lsbs: Vec<Vec<PosPair>>,
+
+	/// Class and weight of every trigram of keys, as made by `TrigramTaxonomy::classify_keys`.
+	trigram_classes: Vec<Option<usize>>,
+	trigram_weights: Vec<f64>,
 
 	weighted_bigrams: BigramData,
 	per_char_trigrams: PerCharTrigrams,
+	trigram_precision: usize,
 
 	pub weights: Weights,
 	pub layouts: IndexMap<String, FastLayout, BuildHasherDefault<fxhash::FxHasher>>,
@@ -206,40 +464,144 @@ impl LayoutGeneration {
 		config: Option<Config>,
 	) -> Result<Self> where P: AsRef<Path> {
 		let config = config.unwrap_or_else(|| Config::new());
+		let shape = config.defaults.shape;
+		config.defaults.keyboard.fits(shape)?;
+		let fingering = config.fingering.clone()
+			.unwrap_or_else(|| config.defaults.keyboard.fingering(shape));
+
+		if fingering.len() != shape.len() {
+			anyhow::bail!("the fingering has {} keys but a {shape} layout has {}", fingering.len(), shape.len())
+		}
+		if let Some(&key) = config.combos.iter().flatten().find(|&&k| k >= shape.len()) {
+			anyhow::bail!("a combo uses key {key} but a {shape} layout has {}", shape.len())
+		}
+		if config.defaults.space && shape.thumbs == 0 {
+			anyhow::bail!("space needs a thumb key, which a {shape} layout doesn't have")
+		}
 		
-		if let Ok(data) = LanguageData::from_file(
-			base_path.as_ref().join("language_data"), language
+		let data_name = if config.defaults.space { format!("{language}_space") } else { language.to_string() };
+		if let Ok(data) = LanguageData::from_file_with_repeat(
+			base_path.as_ref().join("language_data"), &data_name, config.repeat_key.as_ref()
 		) {
 			let mut chars_for_generation = chars_for_generation(language);
+			if let Some(repeat) = &config.repeat_key && !chars_for_generation.contains(&repeat.key) {
+				// the repeat key takes the place of the least common character
+				if chars_for_generation.len() >= shape.len() {
+					let least_common = chars_for_generation.iter()
+						.position_min_by(|a, b| {
+							let a = data.characters.get(a).unwrap_or(&0.0);
+							let b = data.characters.get(b).unwrap_or(&0.0);
+							a.partial_cmp(b).unwrap()
+						})
+						.unwrap();
+					chars_for_generation.remove(least_common);
+				}
+				chars_for_generation.push(repeat.key);
+			}
+			if chars_for_generation.len() < shape.len() {
+				Self::fill_chars_for_generation(&mut chars_for_generation, &data, shape)?;
+			}
 			chars_for_generation.sort_by(|a, b| {
 				let a = data.characters.get(a).unwrap_or(&0.0);
 				let b = data.characters.get(b).unwrap_or(&0.0);
 				b.partial_cmp(a).unwrap()
 			});
+			let mut by_freq = data.characters.iter()
+				.filter(|(c, _)| *c != ' ' && !chars_for_generation.contains(c))
+				.collect::<Vec<_>>();
+			by_freq.sort_by(|(_, f1), (_, f2)| f2.partial_cmp(f1).unwrap());
+			let chars_for_combos = by_freq.into_iter()
+				.map(|(c, _)| *c)
+				.take(config.combos.len())
+				.collect::<Vec<_>>();
+
 			let possible_chars = data.characters.iter()
 				.map(|(c, _)| *c)
+				.chain(chars_for_generation.iter().copied())
+				.unique()
 				.collect::<Vec<_>>();
+
+			let keyboard = &config.defaults.keyboard;
+			let fspeed_vals = get_fspeed(
+				config.weights.lateral_penalty, &config.weights.finger_speed.per_finger(), keyboard, shape, &fingering
+			);
+			let mut col_ranges = [(0, 0); FINGER_COUNT];
+			for (i, (PosPair(i1, _), _)) in fspeed_vals.iter().enumerate() {
+				let col = fingering[*i1];
+				if col_ranges[col].1 == 0 {
+					col_ranges[col].0 = i;
+				}
+				col_ranges[col].1 += 1;
+			}
+			let col_keys: [Vec<usize>; FINGER_COUNT] = std::array::from_fn(|col| {
+				(0..shape.len()).filter(|&i| fingering[i] == col).collect()
+			});
+
+			let trigram_taxonomy = config.trigram_classes
+				.unwrap_or_else(|| TrigramTaxonomy::from_weights(&config.weights));
+			let trigram_classes = trigram_taxonomy.classify_keys(shape, &fingering);
+			let trigram_weights = trigram_classes.iter()
+				.map(|c| c.map_or(0.0, |c| trigram_taxonomy.classes[c].weight))
+				.collect();
+
+			let heatmap = config.weights.heatmap;
+			let effort_map = match config.defaults.effort {
+				EffortModel::Heatmap => get_effort_map(heatmap, keyboard, shape),
+				EffortModel::Travel => get_travel_effort_map(heatmap, keyboard, shape, &fingering)
+			};
+
+			let lsb_indices = get_lsb_indices(keyboard, shape, &fingering);
+			let key_lsbs = (0..shape.len())
+				.map(|i| lsb_indices.iter().filter(|p| p.0 == i || p.1 == i).copied().collect())
+				.collect();
+
+			let scissor_indices = get_scissor_indices(keyboard, shape, &fingering);
+			let mut affects_scissor = vec![false; shape.len()];
+			for PosPair(i1, i2) in scissor_indices.iter() {
+				affects_scissor[*i1] = true;
+				affects_scissor[*i2] = true;
+			}
 			
 			Ok(
 				Self {
 					language: language.to_string(),
 					chars_for_generation,
+					combos: config.combos,
+					chars_for_combos,
 					weighted_bigrams: Self::weighted_bigrams(&data, &config.weights),
 					per_char_trigrams: Self::per_char_trigrams(
 						&data.trigrams,
 						&possible_chars,
 						config.defaults.trigram_precision
 					),
+					trigram_precision: config.defaults.trigram_precision,
 					data,
 
-					fspeed_vals: get_fspeed(config.weights.lateral_penalty),
-					effort_map: get_effort_map(config.weights.heatmap, config.defaults.keyboard_type),
-					scissor_indices: get_scissor_indices(),
+					fspeed_vals,
+					col_ranges,
+					col_keys,
+					effort_map,
+					affects_scissor,
+					scissor_indices,
+					row_jump_indices: get_row_jump_indices(shape, &fingering),
+					lsb_indices,
+					key_lsbs,
+					possible_swaps: shape.possible_swaps(),
+					trigram_taxonomy,
+					constraints: config.constraints,
+					optimizer: config.defaults.optimizer,
+					annealing: config.annealing,
+					trigram_classes,
+					trigram_weights,
+					fingering,
+					shape,
 					
 					weights: config.weights,
 					layouts: IndexMap::default()
 				}
 			)
+		} else if config.defaults.space {
+			anyhow::bail!("Getting language data failed, {data_name} has to be generated with load_text::load_with_space first")
 		} else {
 			anyhow::bail!("Getting language data failed")
 		}
@@ -253,16 +615,73 @@ impl LayoutGeneration {
 		if let Ok(paths) = std::fs::read_dir(&language_dir_path) {
 			let valid = paths
 				.flatten()
-				.filter(|p| is_kb_file(p))
+				.filter(|p| is_kb_file(p) || import::is_importable(p.path()))
 				.collect::<Vec<_>>();
 
 			for entry in valid {
-				if let Some(name) = layout_name(&entry) {
+				if let Some(name) = layout_name(&entry) && import::is_importable(entry.path()) {
+					let imported = match import::import_file(entry.path()) {
+						Ok(imported) => imported,
+						Err(e) => {
+							println!("layout {name} couldn't be imported: {e}");
+							continue;
+						}
+					};
+					if imported.layout.shape != self.shape {
+						println!("layout {name} is {} but the generator is set to {}", imported.layout.shape, self.shape);
+						continue;
+					}
+					if !imported.unmapped.is_empty() {
+						println!("layout {name} has keys that aren't on the matrix: {}", imported.unmapped.join(", "));
+					}
+					let mut layout = FastLayout::from((imported.layout.matrix, self.shape, self.fingering.clone()));
+					layout.score = self.score(&layout);
+					res.insert(name, layout);
+				} else if let Some(name) = layout_name(&entry) {
 					let content = std::fs::read_to_string(entry.path())?;
-					let layout_str = format_layout_str(content);
 
-					if let Ok(mut layout) = FastLayout::try_from(layout_str.as_str()) {
-						layout.score = self.score(&layout);
+					let layers = layers_from_kb(&content, self.shape);
+
+					if let Some((layout_str, shape)) = format_layout_str(content.clone()) {
+						if shape != self.shape {
+							println!("layout {name} is {shape} but the generator is set to {}", self.shape);
+							continue;
+						}
+						let layers = match layers {
+							Ok(layers) => layers,
+							Err(e) => {
+								println!("layout {name} has invalid layers: {e}");
+								continue;
+							}
+						};
+						let matrix = layout_str.chars().collect::<Vec<_>>();
+						let combos = match combos_from_kb(&content, &matrix) {
+							Ok(combos) => combos,
+							Err(e) => {
+								println!("layout {name} has invalid combos: {e}");
+								continue;
+							}
+						};
+						let mut layout = FastLayout::from((matrix, shape, self.fingering.clone()))
+							.with_layers(layers)
+							.with_combos(combos);
+
+						if let Some(layer) = layout.layers.iter()
+							.find(|l| layout.char_to_finger.get(&l.key).is_none()) {
+							println!("layout {name} doesn't have the key for layer {}", layer.name);
+							continue;
+						}
+						layout.score = if layout.layers.is_empty() {
+							self.score(&layout)
+						} else {
+							match self.with_layers(&layout) {
+								Ok(generator) => generator.score(&layout),
+								Err(e) => {
+									println!("layout {name} can't be scored with its layers: {e}");
+									continue;
+								}
+							}
+						};
 						res.insert(name, layout);
 					} else {
 						println!("layout {} is not formatted correctly", name);
@@ -279,31 +698,203 @@ impl LayoutGeneration {
 		Ok(res)
 	}
 
+	/// A generator for analyzing `layout` with its layers, where every character on a layer is typed
+	/// as its layer key followed by the key it sits on. Fails for layers none of whose characters are
+	/// in the data, like shift layers with data that only has lowercase letters and unshifted symbols.
+	pub fn with_layers(&self, layout: &FastLayout) -> Result<Self> {
+		let layer_keys = layout.layer_keys();
+		for layer in &layout.layers {
+			if !layer.matrix.iter().any(|c| layer_keys.contains_key(c) && self.data.characters.get(c).is_some()) {
+				anyhow::bail!(
+					"none of the characters on layer {} are in the {} data, so it doesn't change any score. \
+					Shifted characters are only in data loaded with Translator::raw(false)",
+					layer.name, self.data.language
+				)
+			}
+		}
+		let data = self.data.with_layers(&layer_keys);
+		let possible_chars = data.characters.iter()
+			.map(|(c, _)| *c)
+			.chain(self.chars_for_generation.iter().copied())
+			.chain(layout.matrix.iter().copied())
+			.unique()
+			.collect::<Vec<_>>();
+
+		Ok(Self {
+			language: self.language.clone(),
+			chars_for_generation: self.chars_for_generation.clone(),
+			combos: self.combos.clone(),
+			chars_for_combos: self.chars_for_combos.clone(),
+			weighted_bigrams: Self::weighted_bigrams(&data, &self.weights),
+			per_char_trigrams: Self::per_char_trigrams(
+				&data.trigrams, &possible_chars, self.trigram_precision
+			),
+			trigram_precision: self.trigram_precision,
+			data,
+
+			fspeed_vals: self.fspeed_vals.clone(),
+			col_ranges: self.col_ranges,
+			col_keys: self.col_keys.clone(),
+			effort_map: self.effort_map.clone(),
+			affects_scissor: self.affects_scissor.clone(),
+			scissor_indices: self.scissor_indices.clone(),
+			row_jump_indices: self.row_jump_indices.clone(),
+			lsb_indices: self.lsb_indices.clone(),
+			key_lsbs: self.key_lsbs.clone(),
+			possible_swaps: self.possible_swaps.clone(),
+			trigram_taxonomy: self.trigram_taxonomy.clone(),
+			constraints: self.constraints.clone(),
+			optimizer: self.optimizer,
+			annealing: self.annealing.clone(),
+			trigram_classes: self.trigram_classes.clone(),
+			trigram_weights: self.trigram_weights.clone(),
+			fingering: self.fingering.clone(),
+			shape: self.shape,
+
+			weights: self.weights.clone(),
+			layouts: IndexMap::default()
+		})
+	}
+
 	pub fn get_layout_stats(&self, layout: &FastLayout) -> LayoutStats {
 		let sfb = self.bigram_percent(layout, "sfbs");
 		let dsfb = self.bigram_percent(layout, "skipgrams");
 		let dsfb2 = self.bigram_percent(layout, "skipgrams2");
 		let dsfb3 = self.bigram_percent(layout, "skipgrams3");
+		let weighted_dsfb = self.bigram_percent(layout, "weighted dsfbs");
 		let cache = self.initialize_cache(layout);
 		let fspeed = cache.fspeed_total;
 		let finger_speed = cache.fspeed;
 		let scissors = self.scissor_score(layout) / self.weights.scissors;
+		let lsbs = self.pair_bigrams(layout, &self.lsb_indices);
+		let [left, right] = self.hand_usage(layout);
+		let hand_balance = if left + right > 0.0 { left / (left + right) } else { 0.5 };
+		let rows = self.row_stats(layout);
 		let trigram_stats = self.trigram_stats(layout, usize::MAX);
+		let repeat = self.repeat_stats(layout);
+		let combos = self.combo_stats(layout);
+		let broken_constraints = self.broken_constraints(layout);
 		
-		LayoutStats { sfb, dsfb, dsfb2, dsfb3, fspeed, finger_speed, scissors, trigram_stats }
+		LayoutStats {
+			sfb, dsfb, dsfb2, dsfb3, weighted_dsfb, fspeed, finger_speed, scissors, lsbs, hand_balance, rows,
+			trigram_stats, repeat, combos, broken_constraints
+		}
 	}
 
+	/// How much the combos of a layout are used, `None` if it doesn't have any.
+	pub fn combo_stats(&self, layout: &FastLayout) -> Option<ComboStats> {
+		if layout.combos.is_empty() {
+			return None
+		}
+		let mut res = ComboStats::default();
+
+		for combo in &layout.combos {
+			res.presses += self.data.characters.get(&combo.output).copied().unwrap_or_default();
+
+			for i in self.combo_finger_keys(layout, combo) {
+				let c = layout.matrix[i];
+				res.sfbs += self.data.bigrams.get(&[c, combo.output]).copied().unwrap_or_default()
+					+ self.data.bigrams.get(&[combo.output, c]).copied().unwrap_or_default();
+			}
+		}
+		Some(res)
+	}
+
+	/// Keys on the fingers a combo presses, which can't start pressing the next key until it's
+	/// done.
+	fn combo_finger_keys<'a>(&self, layout: &'a FastLayout, combo: &'a Combo) -> impl Iterator<Item = usize> + 'a {
+		(0..layout.matrix.len())
+			.filter(|&i| combo.keys.iter().any(|&k| layout.fingering[k] == layout.fingering[i]))
+	}
+
+	/// The effort of the keys of every combo along with the cost of pressing a combo, and the
+	/// bigrams between a combo and the keys on the fingers it occupies, which count like sfbs.
+	pub fn combo_score(&self, layout: &FastLayout) -> f64 {
+		layout.combos.iter().map(|combo| self.single_combo_score(layout, combo)).sum()
+	}
+
+	/// Whether a swap changes the score of a combo, which it does when it moves a key on one of the
+	/// fingers the combo presses.
+	fn affects_combo(layout: &FastLayout, combo: &Combo, &PosPair(i1, i2): &PosPair) -> bool {
+		combo.keys.iter()
+			.any(|&k| layout.fingering[k] == layout.fingering[i1] || layout.fingering[k] == layout.fingering[i2])
+	}
+
+	fn single_combo_score(&self, layout: &FastLayout, combo: &Combo) -> f64 {
+		let freq = self.data.characters.get(&combo.output).copied().unwrap_or_default();
+		let effort = combo.keys.iter().map(|&k| self.effort_map[k]).sum::<f64>();
+		let mut res = freq * (effort + self.weights.combo);
+
+		for i in self.combo_finger_keys(layout, combo) {
+			let c = layout.matrix[i];
+			res += self.weighted_bigrams.get(&[c, combo.output]).copied().unwrap_or_default()
+				+ self.weighted_bigrams.get(&[combo.output, c]).copied().unwrap_or_default();
+		}
+		res
+	}
+
+	/// The sfbs and same key presses the repeat key types instead, `None` without a repeat key.
+	pub fn repeat_stats(&self, layout: &FastLayout) -> Option<RepeatStats> {
+		if self.data.repeated.is_empty() {
+			return None
+		}
+		let mut res = RepeatStats::default();
+
+		for (&[c1, c2], &f) in &self.data.repeated {
+			if c1 == c2 {
+				res.same_key += f;
+			} else if let (Some(f1), Some(f2)) = (layout.char_to_finger.get(&c1), layout.char_to_finger.get(&c2))
+				&& f1 == f2 {
+				res.sfbs += f;
+			}
+		}
+		Some(res)
+	}
+
+	/// Scores every step of the fewest swaps that turn `from` into `to`. `None` if the layouts
+	/// don't have the same keys.
+	pub fn swap_path(&self, from: &FastLayout, to: &FastLayout) -> Option<Vec<SwapStep>> {
+		let mut layout = from.clone();
+		let mut score = self.score(&layout);
+		let mut res = Vec::new();
+
+		for swap in from.swaps_to(to)? {
+			layout.swap_pair(&swap);
+			let new_score = self.score(&layout);
+
+			res.push(SwapStep {
+				swap,
+				score: new_score,
+				score_change: new_score - score,
+				stats: self.get_layout_stats(&layout)
+			});
+			score = new_score;
+		}
+		Some(res)
+	}
+
+	/// How much of a kind of bigram is typed with one finger. Weighted dsfbs add up the dsfbs of every
+	/// skip distance, each weighted by its dsfb ratio.
 	pub fn bigram_percent(&self, layout: &FastLayout, bigram_type: &str) -> f64 {
 		let data = match bigram_type {
 			"bigram" | "bigrams" | "sfb" | "sfbs" => &self.data.bigrams,
 			"skipgram" | "skipgrams" | "dsfb" | "dsfbs" => &self.data.skipgrams,
 			"skipgram2" | "skipgrams2" | "dsfb2" | "dsfbs2" => &self.data.skipgrams2,
 			"skipgram3" | "skipgrams3" | "dsfb3" | "dsfbs3" => &self.data.skipgrams3,
+			"weighted dsfb" | "weighted dsfbs" => {
+				return self.data.skipgrams().iter()
+					.zip(self.weights.dsfb_ratios())
+					.map(|(data, ratio)| self.same_finger_percent(layout, data) * ratio)
+					.sum()
+			},
 			_ => panic!("bigram type {bigram_type} does not exist!")
 		};
+		self.same_finger_percent(layout, data)
+	}
 
+	fn same_finger_percent(&self, layout: &FastLayout, data: &BigramData) -> f64 {
 		let mut res = 0.0;
-		for (PosPair(i1, i2), _) in self.fspeed_vals {
+		for &(PosPair(i1, i2), _) in &self.fspeed_vals {
 			let c1 = unsafe { layout.cu(i1) };
 			let c2 = unsafe { layout.cu(i2) };
 			res += data.get(&[c1, c2]).unwrap_or_else(|| &0.0);
@@ -313,24 +904,160 @@ impl LayoutGeneration {
 	}
 
 	pub fn trigram_stats(&self, layout: &FastLayout, trigram_precision: usize) -> TrigramStats {
-		let mut freqs = TrigramStats::default();
+		let mut stats = TrigramStats {
+			classes: self.trigram_taxonomy.classes.iter().map(|c| (c.name.clone(), 0.0)).collect(),
+			..Default::default()
+		};
 		for (trigram, freq) in self.data.trigrams.iter().take(trigram_precision) {
-			match layout.get_trigram_pattern(trigram) {
-				TrigramPattern::Alternate => freqs.alternates += freq,
-				TrigramPattern::AlternateSfs => freqs.alternates_sfs += freq,
-				TrigramPattern::Inroll => freqs.inrolls += freq,
-				TrigramPattern::Outroll => freqs.outrolls += freq,
-				TrigramPattern::Onehand => freqs.onehands += freq,
-				TrigramPattern::Redirect => freqs.redirects += freq,
-				TrigramPattern::BadRedirect => freqs.bad_redirects += freq,
-				TrigramPattern::Sfb => freqs.sfbs += freq,
-				TrigramPattern::BadSfb => freqs.bad_sfbs += freq,
-				TrigramPattern::Sft => freqs.sfts += freq,
-				TrigramPattern::Other => freqs.other += freq,
-				TrigramPattern::Invalid => freqs.invalid += freq
+			match self.trigram_index(layout, trigram).map(|i| self.trigram_classes[i]) {
+				Some(Some(class)) => *stats.classes.get_index_mut(class).unwrap().1 += freq,
+				Some(None) => stats.other += freq,
+				None => stats.invalid += freq
 			}
 		}
-		freqs
+		stats
+	}
+
+	/// The `n` bigrams, skipgrams, scissors and lsbs and the `n` trigrams of every trigram class that make
+	/// up the most of their metric on a layout.
+	pub fn offenders(&self, layout: &FastLayout, n: usize) -> Offenders {
+		let sfb_pairs = self.fspeed_vals.iter().map(|(pair, _)| *pair).collect::<Vec<_>>();
+
+		let mut trigrams = self.trigram_taxonomy.classes.iter()
+			.map(|c| (c.name.clone(), Vec::new()))
+			.collect::<IndexMap<_, _>>();
+		for (trigram, freq) in self.data.trigrams.iter() {
+			if let Some(class) = self.trigram_index(layout, trigram).and_then(|i| self.trigram_classes[i]) {
+				trigrams[class].push((trigram.iter().collect(), *freq));
+			}
+		}
+
+		Offenders {
+			sfbs: top_offenders(self.bigrams_on(layout, &self.data.bigrams, &sfb_pairs), n),
+			dsfbs: top_offenders(self.bigrams_on(layout, &self.data.skipgrams, &sfb_pairs), n),
+			scissors: top_offenders(self.bigrams_on(layout, &self.data.bigrams, &self.scissor_indices), n),
+			lsbs: top_offenders(self.bigrams_on(layout, &self.data.bigrams, &self.lsb_indices), n),
+			trigrams: trigrams.into_iter()
+				.map(|(class, trigrams)| (class, top_offenders(trigrams, n)))
+				.collect()
+		}
+	}
+
+	/// Every bigram of `data` typed on one of `pairs` of keys, in both directions.
+	fn bigrams_on(&self, layout: &FastLayout, data: &BigramData, pairs: &[PosPair]) -> Vec<(String, f64)> {
+		let mut res = Vec::new();
+		for &PosPair(i1, i2) in pairs {
+			let (c1, c2) = (layout.c(i1), layout.c(i2));
+			for bigram in [[c1, c2], [c2, c1]] {
+				if let Some(&freq) = data.get(&bigram) && freq > 0.0 {
+					res.push((bigram.iter().collect(), freq));
+				}
+			}
+		}
+		res
+	}
+
+	/// Where a trigram is in `trigram_classes` and `trigram_weights`, `None` if one of its characters
+	/// isn't on the layout.
+	#[inline(always)]
+	fn trigram_index(&self, layout: &FastLayout, [c1, c2, c3]: &[char; 3]) -> Option<usize> {
+		let len = layout.matrix.len();
+		let k1 = *layout.char_to_key.get(c1)?;
+		let k2 = *layout.char_to_key.get(c2)?;
+		let k3 = *layout.char_to_key.get(c3)?;
+		Some((k1 * len + k2) * len + k3)
+	}
+
+	/// The components of the score of a layout and what the character on every key contributes to
+	/// them.
+	pub fn score_breakdown(&self, layout: &FastLayout) -> ScoreBreakdown {
+		let mut keys = layout.matrix.iter()
+			.map(|&c| (c, ScoreComponents::default()))
+			.collect::<Vec<_>>();
+
+		for (i, (_, key)) in keys.iter_mut().enumerate() {
+			key.effort = -self.char_effort(layout, i);
+		}
+		for col in 0..FINGER_COUNT {
+			let usage = self.col_usage(layout, col);
+			let freq = |i: usize| self.data.characters.get(&layout.matrix[i]).copied().unwrap_or_default();
+			let total = self.col_keys[col].iter().map(|&i| freq(i)).sum::<f64>();
+
+			if usage > 0.0 && total > 0.0 {
+				for &i in &self.col_keys[col] {
+					keys[i].1.usage = -usage * freq(i) / total;
+				}
+			}
+		}
+		for (pair, dist) in &self.fspeed_vals {
+			let fspeed = self.pair_fspeed(layout, pair, *dist) / 2.0;
+			keys[pair.0].1.fspeed -= fspeed;
+			keys[pair.1].1.fspeed -= fspeed;
+		}
+		for &PosPair(i1, i2) in &self.scissor_indices {
+			let (c1, c2) = (layout.matrix[i1], layout.matrix[i2]);
+			let freq = self.data.bigrams.get(&[c1, c2]).unwrap_or(&0.0)
+				+ self.data.bigrams.get(&[c2, c1]).unwrap_or(&0.0);
+			let scissors = freq * self.weights.scissors / 2.0;
+			keys[i1].1.scissors -= scissors;
+			keys[i2].1.scissors -= scissors;
+		}
+		for pair in &self.lsb_indices {
+			let lsbs = self.pair_bigrams(layout, std::slice::from_ref(pair)) * self.weights.lsbs / 2.0;
+			keys[pair.0].1.lsbs -= lsbs;
+			keys[pair.1].1.lsbs -= lsbs;
+		}
+		for (trigram, freq) in &self.data.trigrams {
+			if let Some(i) = self.trigram_index(layout, trigram) {
+				let score = self.trigram_weights[i] * freq / 3.0;
+				for k in trigram.iter().filter_map(|c| layout.char_to_key.get(c)) {
+					keys[*k].1.trigrams += score;
+				}
+			}
+		}
+		for combo in &layout.combos {
+			let score = self.single_combo_score(layout, combo) / combo.keys.len() as f64;
+			for &k in &combo.keys {
+				keys[k].1.combos -= score;
+			}
+		}
+
+		for constraint in self.broken_constraints(layout) {
+			let on_layout = constraint.chars().iter()
+				.filter_map(|c| layout.char_to_key.get(c).copied())
+				.collect::<Vec<_>>();
+			for &k in &on_layout {
+				keys[k].1.constraints -= constraint.penalty() / on_layout.len() as f64;
+			}
+		}
+
+		let usage = self.hand_usage(layout);
+		let busier = if usage[0] >= usage[1] { 0 } else { 1 };
+		let hand_balance = self.hand_balance_score(usage);
+		if usage[busier] > 0.0 {
+			for (i, (c, key)) in keys.iter_mut().enumerate() {
+				if Self::hand(self.fingering[i]) == busier {
+					let freq = self.data.characters.get(c).copied().unwrap_or_default();
+					key.hand_balance = -hand_balance * freq / usage[busier];
+				}
+			}
+		}
+
+		let usage = self.row_usage(layout);
+		for row in [0, 2] {
+			let penalty = self.row_use_score(usage) - self.row_use_score(Self::without_row(usage, row));
+			if penalty > 0.0 {
+				for (i, (c, key)) in keys.iter_mut().enumerate() {
+					if self.shape.alpha_row(i) == Some(row) {
+						let freq = self.data.characters.get(c).copied().unwrap_or_default();
+						key.row_use = -penalty * freq / usage[row];
+					}
+				}
+			}
+		}
+
+		let total = keys.iter().fold(ScoreComponents::default(), |acc, (_, key)| acc + *key);
+		ScoreBreakdown { total, keys }
 	}
 
 	pub fn score(&self, layout: &FastLayout) -> f64 {
@@ -339,64 +1066,166 @@ impl LayoutGeneration {
 			.map(|i| self.char_effort(layout, i))
 			.sum::<f64>();
 		
-		let fspeed_usage = (0..8)
+		let fspeed_usage = (0..FINGER_COUNT)
 			.into_iter()
 			.map(|col| self.col_usage(layout, col) + self.col_fspeed(layout, col))
 			.sum::<f64>();
 
 		let scissors = self.scissor_score(layout);
+		let lsbs = self.lsb_score(layout);
 		let trigram_score = self.trigram_score_iter(layout, &self.data.trigrams);
+		let combos = self.combo_score(layout);
+		let constraints = self.constraint_penalty(layout);
+		let hand_balance = self.hand_balance_score(self.hand_usage(layout));
+		let row_use = self.row_use_score(self.row_usage(layout));
+
+		trigram_score - effort - fspeed_usage - scissors - lsbs - combos - constraints - hand_balance - row_use
+	}
+
+	/// How much the top, home and bottom row of the alpha block are used.
+	pub fn row_usage(&self, layout: &FastLayout) -> [f64; 3] {
+		let mut res = [0.0; 3];
+		for (i, c) in layout.matrix.iter().enumerate() {
+			if let Some(row) = self.shape.alpha_row(i) {
+				res[row] += self.data.characters.get(c).copied().unwrap_or_default();
+			}
+		}
+		res
+	}
+
+	#[inline]
+	fn row_use_score(&self, [top, _, bottom]: [f64; 3]) -> f64 {
+		let max = &self.weights.max_row_use;
+		max.penalty * ((top - max.top).max(0.0) + (bottom - max.bottom).max(0.0))
+	}
+
+	fn without_row(mut usage: [f64; 3], row: usize) -> [f64; 3] {
+		usage[row] = 0.0;
+		usage
+	}
+
+	/// The row usage of a layout that `swap` was just made on, when it was `usage` before.
+	#[inline]
+	fn swapped_row_usage(&self, layout: &FastLayout, swap: &PosPair, mut usage: [f64; 3]) -> [f64; 3] {
+		let PosPair(i1, i2) = *swap;
+		let (r1, r2) = (self.shape.alpha_row(i1), self.shape.alpha_row(i2));
+
+		if r1 != r2 {
+			let freq = |i: usize| self.data.characters.get(&layout.matrix[i]).copied().unwrap_or_default();
+			let moved = freq(i1) - freq(i2);
+			if let Some(r1) = r1 {
+				usage[r1] += moved;
+			}
+			if let Some(r2) = r2 {
+				usage[r2] -= moved;
+			}
+		}
+		usage
+	}
 
-        let mut score = trigram_score - effort - fspeed_usage - scissors;
-
-        // if let Some(f) = layout.char_to_finger.get(&'o') {
-        //     if !(*f == 2) {
-        //         score *= if score > 0. { -1.} else { 1. }
-        //     }
-        // }
-        if 
-           let Some(e) = layout.char_to_finger.get(&'e') &&
-           let Some(t) = layout.char_to_finger.get(&'t') &&
-           let Some(a) = layout.char_to_finger.get(&'a') &&
-           let Some(o) = layout.char_to_finger.get(&'o') &&
-           let Some(i) = layout.char_to_finger.get(&'i') &&
-           let Some(n) = layout.char_to_finger.get(&'n') && 
-           let Some(s) = layout.char_to_finger.get(&'s') &&
-           let Some(h) = layout.char_to_finger.get(&'h') &&
-           let Some(r) = layout.char_to_finger.get(&'r') &&
-           let Some(d) = layout.char_to_finger.get(&'d') &&
-           let Some(l) = layout.char_to_finger.get(&'l') &&
-           let Some(u) = layout.char_to_finger.get(&'u')
-        {
-            if 
-               // !(*r == *n && *r != *l) ||
-               // !((*e < 4 && *h < 4) || (*e >= 4 && *h >= 4)) ||
-               !(*e == 2 || *e == 5)
-            || !((*n < 4 && *e < 4) || (*n >= 4 && *e >= 4))
-            || (*n < 4 && *r < 4)
-            || (*n >= 4 && *r >= 4)
-            {
-                score *= if score > 0. { -1.} else { 1. }
-            }
-
-            let mut nrts = vec![
-                (*e < 4),
-                (*a < 4),
-                (*o < 4),
-                (*i < 4),
-                ((*n < 4 || *h < 4) && (((*n < 4) && (*h >= 4)) || ((*n >= 4) && (*h < 4))))
-            ];
-            nrts.sort();
-            match nrts.as_slice() {
-                [true, true, true, true, false]
-            |   [false, true, true, true, true]
-            |   [false, false, false, false, true]
-            |   [true, false, false, false, false] => (),
-            | _ =>
-                score *= if score > 0. { -1.} else { 1. }
-            }
-        }
-        score
+	pub fn row_stats(&self, layout: &FastLayout) -> RowStats {
+		let mut rows = [RowUsage::default(); 3];
+		let total = layout.matrix.iter()
+			.map(|c| self.data.characters.get(c).copied().unwrap_or_default())
+			.sum::<f64>();
+
+		if total > 0.0 {
+			for (i, c) in layout.matrix.iter().enumerate() {
+				if let Some(row) = self.shape.alpha_row(i) {
+					let freq = self.data.characters.get(c).copied().unwrap_or_default() / total;
+					if is_left_hand(self.fingering[i]) {
+						rows[row].left += freq;
+					} else {
+						rows[row].right += freq;
+					}
+				}
+			}
+		}
+		let row_jumps = self.bigrams_on(layout, &self.data.bigrams, &self.row_jump_indices)
+			.into_iter()
+			.map(|(_, freq)| freq)
+			.sum();
+
+		let [top, home, bottom] = rows;
+		RowStats { top, home, bottom, row_jumps }
+	}
+
+	/// 0 for the left hand and 1 for the right one.
+	#[inline(always)]
+	fn hand(finger: usize) -> usize {
+		!is_left_hand(finger) as usize
+	}
+
+	/// How much the left and right hand are used.
+	pub fn hand_usage(&self, layout: &FastLayout) -> [f64; 2] {
+		let mut res = [0.0; 2];
+		for (c, &finger) in layout.matrix.iter().zip(&self.fingering) {
+			res[Self::hand(finger)] += self.data.characters.get(c).copied().unwrap_or_default();
+		}
+		res
+	}
+
+	#[inline]
+	fn hand_balance_score(&self, [left, right]: [f64; 2]) -> f64 {
+		self.weights.hand_balance * (left - right).abs()
+	}
+
+	/// The hand usage of a layout that `swap` was just made on, when it was `usage` before.
+	#[inline]
+	fn swapped_hand_usage(&self, layout: &FastLayout, swap: &PosPair, mut usage: [f64; 2]) -> [f64; 2] {
+		let PosPair(i1, i2) = *swap;
+		let (h1, h2) = (Self::hand(self.fingering[i1]), Self::hand(self.fingering[i2]));
+
+		if h1 != h2 {
+			let freq = |i: usize| self.data.characters.get(&layout.matrix[i]).copied().unwrap_or_default();
+			let moved = freq(i1) - freq(i2);
+			usage[h1] += moved;
+			usage[h2] -= moved;
+		}
+		usage
+	}
+
+	/// The constraints a layout breaks.
+	pub fn broken_constraints(&self, layout: &FastLayout) -> Vec<Constraint> {
+		self.constraints.iter()
+			.filter(|c| c.is_broken(&layout.char_to_finger))
+			.cloned()
+			.collect()
+	}
+
+	#[inline]
+	pub fn constraint_penalty(&self, layout: &FastLayout) -> f64 {
+		self.constraints.iter()
+			.filter(|c| c.is_broken(&layout.char_to_finger))
+			.map(|c| c.penalty())
+			.sum()
+	}
+
+	/// Tops up the characters from `languages_default.cfg` with the most frequent remaining ones
+	/// when the shape has more keys than the 30 in the config. Numbers are used after that, as the
+	/// language data usually doesn't contain them. Space is only considered for thumb keys.
+	fn fill_chars_for_generation(
+		chars: &mut Vec<char>, data: &LanguageData, shape: LayoutShape
+	) -> Result<()> {
+		let mut by_freq = data.characters.iter()
+			.filter(|(c, _)| (*c != ' ' || shape.thumbs > 0) && !chars.contains(c))
+			.map(|&(c, f)| (c, f))
+			.collect::<Vec<_>>();
+		by_freq.sort_by(|(_, f1), (_, f2)| f2.partial_cmp(f1).unwrap());
+
+		let fill = by_freq.into_iter()
+			.map(|(c, _)| c)
+			.chain("1234567890".chars())
+			.unique()
+			.filter(|c| !chars.contains(c))
+			.take(shape.len() - chars.len())
+			.collect::<Vec<_>>();
+		chars.extend(fill);
+
+		if chars.len() < shape.len() {
+			anyhow::bail!("{} doesn't have enough characters to fill a {shape} layout", data.language)
+		}
+		Ok(())
 	}
 
 	fn weighted_bigrams(data: &LanguageData, weights: &Weights) -> BigramData {
@@ -408,10 +1237,11 @@ impl LayoutGeneration {
 			.map(|(&c1, &c2)| {
 				let bigram = [c1, c2];
 				let sfb = data.bigrams.get(&bigram).unwrap_or(&0.0);
-				let dsfb = data.skipgrams.get(&bigram).unwrap_or(&0.0) * weights.dsfb_ratio;
-				let dsfb2 = data.skipgrams2.get(&bigram).unwrap_or(&0.0) * weights.dsfb_ratio2;
-				let dsfb3 = data.skipgrams3.get(&bigram).unwrap_or(&0.0) * weights.dsfb_ratio3;
-				(bigram, (sfb + dsfb + dsfb2 + dsfb3) * weights.fspeed)
+				let dsfbs = data.skipgrams().iter()
+					.zip(weights.dsfb_ratios())
+					.map(|(skipgrams, ratio)| skipgrams.get(&bigram).unwrap_or(&0.0) * ratio)
+					.sum::<f64>();
+				(bigram, (sfb + dsfbs) * weights.fspeed * weights.finger_speed.base)
 			})
 			.filter(|(_, f)| *f != 0.0)
 			.collect()
@@ -457,29 +1287,13 @@ impl LayoutGeneration {
 	#[inline]
 	fn trigram_score_iter<'a, T>(&self, layout: &FastLayout, trigrams: T) -> f64
 	where T: IntoIterator<Item=&'a ([char; 3], f64)> {
-		let mut freqs = TrigramStats::default();
+		let mut score = 0.0;
 
 		for (trigram, freq) in trigrams {
-			match layout.get_trigram_pattern(trigram) {
-				TrigramPattern::Alternate => freqs.alternates += freq,
-				TrigramPattern::AlternateSfs => freqs.alternates_sfs += freq,
-				TrigramPattern::Inroll => freqs.inrolls += freq,
-				TrigramPattern::Outroll => freqs.outrolls += freq,
-				TrigramPattern::Onehand => freqs.onehands += freq,
-				TrigramPattern::Redirect => freqs.redirects += freq,
-				TrigramPattern::BadRedirect => freqs.bad_redirects += freq,
-				_ => {}
+			if let Some(i) = self.trigram_index(layout, trigram) {
+				score += self.trigram_weights[i] * freq;
 			}
 		}
-
-		let mut score = 0.0;
-		score += self.weights.inrolls * freqs.inrolls;
-		score += self.weights.outrolls * freqs.outrolls;
-		score += self.weights.onehands * freqs.onehands;
-		score += self.weights.alternates * freqs.alternates;
-		score += self.weights.alternates_sfs * freqs.alternates_sfs;
-		score -= self.weights.redirects * freqs.redirects;
-		score -= self.weights.bad_redirects * freqs.bad_redirects;
 		score
 	}
 
@@ -494,9 +1308,16 @@ impl LayoutGeneration {
 		}
 	}
 
+	#[inline]
+	fn affects_scissor(&self, swap: &PosPair) -> bool {
+		unsafe {
+			*self.affects_scissor.get_unchecked(swap.0) || *self.affects_scissor.get_unchecked(swap.1)
+		}
+	}
+
 	fn scissor_score(&self, layout: &FastLayout) -> f64 {
 		let mut res = 0.0;
-		for PosPair(i1, i2) in self.scissor_indices {
+		for &PosPair(i1, i2) in &self.scissor_indices {
 			let c1 = layout.matrix[i1];
 			let c2 = layout.matrix[i2];
 			res += self.data.bigrams.get(&[c1, c2]).unwrap_or_else(|| &0.0);
@@ -506,35 +1327,43 @@ impl LayoutGeneration {
 		res * self.weights.scissors
 	}
 
+	/// Frequency of the bigrams on `pairs` of keys, in both directions.
+	fn pair_bigrams(&self, layout: &FastLayout, pairs: &[PosPair]) -> f64 {
+		pairs.iter()
+			.map(|&PosPair(i1, i2)| {
+				let (c1, c2) = (layout.matrix[i1], layout.matrix[i2]);
+				self.data.bigrams.get(&[c1, c2]).unwrap_or(&0.0) + self.data.bigrams.get(&[c2, c1]).unwrap_or(&0.0)
+			})
+			.sum()
+	}
+
+	fn lsb_score(&self, layout: &FastLayout) -> f64 {
+		self.pair_bigrams(layout, &self.lsb_indices) * self.weights.lsbs
+	}
+
+	/// The part of the lsb score on the keys of a swap, which is all a swap changes.
+	fn lsb_swap_score(&self, layout: &FastLayout, &PosPair(i1, i2): &PosPair) -> f64 {
+		let on_i1 = self.pair_bigrams(layout, &self.key_lsbs[i1]);
+		let only_on_i2 = self.key_lsbs[i2].iter()
+			.filter(|p| p.0 != i1 && p.1 != i1)
+			.map(|p| self.pair_bigrams(layout, std::slice::from_ref(p)))
+			.sum::<f64>();
+		(on_i1 + only_on_i2) * self.weights.lsbs
+	}
+
 	fn col_usage(&self, layout: &FastLayout, col: usize) -> f64 {
 		let mut res = 0.0;
-		match col {
-			0 | 1 | 2 => {
-				for c in [unsafe { layout.cu(col) }, layout.c(col+10), layout.c(col+20)] {
-					res += *self.data.characters.get(&c).unwrap_or_else(|| &0.0);
-				}
-			},
-			3 | 4 => {
-				let col = (col - 3) * 2 + 3;
-				for c in [unsafe { layout.cu(col) }, layout.c(col+10), layout.c(col+20),
-								layout.c(col+1), layout.c(col+11), layout.c(col+21)] {
-					res += *self.data.characters.get(&c).unwrap_or_else(|| &0.0);
-				}
-			},
-			5 | 6 | 7 => {
-				let col = col + 2;
-				for c in [unsafe { layout.cu(col) }, layout.c(col+10), layout.c(col+20)] {
-					res += *self.data.characters.get(&c).unwrap_or_else(|| &0.0);
-				}
-			},
-			_ => unsafe { unreachable_unchecked() }
-		};
+		for &i in &self.col_keys[col] {
+			let c = unsafe { layout.cu(i) };
+			res += *self.data.characters.get(&c).unwrap_or_else(|| &0.0);
+		}
 
 		self.weights.max_finger_use.penalty * match col {
 			0 | 7 => (res - self.weights.max_finger_use.pinky).max(0.0),
 			1 | 6 => (res - self.weights.max_finger_use.ring).max(0.0),
 			2 | 5 => (res - self.weights.max_finger_use.middle).max(0.0),
 			3 | 4 => (res - self.weights.max_finger_use.index).max(0.0),
+			LEFT_THUMB | RIGHT_THUMB => (res - self.weights.max_finger_use.thumb).max(0.0),
 			_ => unsafe { unreachable_unchecked() }
 		}
 	}
@@ -551,13 +1380,13 @@ impl LayoutGeneration {
 	}
 
 	#[inline(always)]
-	pub(self) const unsafe fn col_to_start_len(col: usize) -> (usize, usize) {
-		*[(0, 3), (3, 3), (6, 3), (18, 15), (33, 15), (9, 3), (12, 3), (15, 3)].get_unchecked(col)
+	pub(self) unsafe fn col_to_start_len(&self, col: usize) -> (usize, usize) {
+		*self.col_ranges.get_unchecked(col)
 	}
 
 	#[inline]
 	fn col_fspeed(&self, layout: &FastLayout, col: usize) -> f64 {
-		let (start, len) = unsafe { Self::col_to_start_len(col) };
+		let (start, len) = unsafe { self.col_to_start_len(col) };
 		let mut res = 0.0;
 
 		for i in start..(start+len) {
@@ -577,14 +1406,17 @@ impl LayoutGeneration {
 	}
 
 	fn initialize_cache(&self, layout: &FastLayout) -> LayoutCache {
-		let mut res = LayoutCache::default();
+		let mut res = LayoutCache {
+			effort: vec![0.0; layout.matrix.len()],
+			..Default::default()
+		};
 
 		for i in 0..layout.matrix.len() {
 			res.effort[i] = self.char_effort(layout, i);
 		}
 		res.effort_total = res.effort.iter().sum();
 
-		for col in 0..8 {
+		for col in 0..FINGER_COUNT {
 			res.usage[col] = self.col_usage(layout, col);
 			res.fspeed[col] = self.col_fspeed(layout, col)
 		}
@@ -592,9 +1424,21 @@ impl LayoutGeneration {
 		res.fspeed_total = res.fspeed.iter().sum();
 
 		res.scissors = self.scissor_score(layout);
+		res.lsbs = self.lsb_score(layout);
 
 		res.trigrams_total = self.trigram_score_iter(layout, self.data.trigrams.iter().take(1000));
 
+		res.constraints = self.constraint_penalty(layout);
+
+		res.hand_usage = self.hand_usage(layout);
+		res.hand_balance = self.hand_balance_score(res.hand_usage);
+
+		res.row_usage = self.row_usage(layout);
+		res.row_use = self.row_use_score(res.row_usage);
+
+		res.combos = layout.combos.iter().map(|combo| self.single_combo_score(layout, combo)).collect();
+		res.combos_total = res.combos.iter().sum();
+
 		res.total_score = res.total_score();
 		
 		res
@@ -605,8 +1449,8 @@ impl LayoutGeneration {
 
 			let PosPair(i1, i2) = *swap;
 
-			let col1 = I_TO_COL[i1];
-			let col2 = I_TO_COL[i2];
+			let col1 = self.fingering[i1];
+			let col2 = self.fingering[i2];
 
 			let fspeed_score = if col1 == col2 {
 				let fspeed = self.col_fspeed(layout, col1);
@@ -635,23 +1479,36 @@ impl LayoutGeneration {
 			let effort_score = cache.effort_total - cache.effort[i1]
 				- cache.effort[i2] + effort1 + effort2;
 
-			let scissors_score = if swap.affects_scissor() {
+			let scissors_score = if self.affects_scissor(swap) {
 				self.scissor_score(layout)
 			} else {
 				cache.scissors
 			};
 
+			let lsbs_end = self.lsb_swap_score(layout, swap);
+
+			let constraints_score = self.constraint_penalty(layout);
+			let hand_balance_score = self.hand_balance_score(
+				self.swapped_hand_usage(layout, swap, cache.hand_usage)
+			);
+			let row_use_score = self.row_use_score(self.swapped_row_usage(layout, swap, cache.row_usage));
+			let combos_score = cache.combos_total + layout.combos.iter().zip(&cache.combos)
+				.filter(|(combo, _)| Self::affects_combo(layout, combo, swap))
+				.map(|(combo, cached)| self.single_combo_score(layout, combo) - cached)
+				.sum::<f64>();
+
 			let _new_heur = cache.trigrams_total - scissors_score - effort_score - usage_score - fspeed_score;
 
-			let trigrams_score = if cache.total_score < (f64::MAX) { //new_heur + new_heur.abs() * 0.0) {
+			let (trigrams_score, lsbs_score) = if cache.total_score < (f64::MAX) { //new_heur + new_heur.abs() * 0.0) {
 				let trigrams_end = self.trigram_char_score(layout, swap);
 				unsafe { layout.swap_no_bounds(swap) };
 				let trigrams_start = self.trigram_char_score(layout, swap);
+				let lsbs_start = self.lsb_swap_score(layout, swap);
 
 				#[cfg(test)]
 				NOT_PRUNED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 				
-				cache.trigrams_total - trigrams_start + trigrams_end
+				(cache.trigrams_total - trigrams_start + trigrams_end, cache.lsbs - lsbs_start + lsbs_end)
 			} else {
 				#[cfg(test)]
 				PRUNED_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -660,18 +1517,20 @@ impl LayoutGeneration {
 				return f64::MIN + 1000.0;
 			};
 
-			trigrams_score - scissors_score - effort_score - usage_score - fspeed_score
+			trigrams_score - scissors_score - lsbs_score - effort_score - usage_score - fspeed_score
+				- constraints_score - hand_balance_score - row_use_score - combos_score
 	}
 
 	fn accept_swap(&self, layout: &mut FastLayout, swap: &PosPair, cache: &mut LayoutCache) {
 		let trigrams_start = self.trigram_char_score(layout, swap);
+		let lsbs_start = self.lsb_swap_score(layout, swap);
 
 		unsafe { layout.swap_no_bounds(swap) };
 
 		let PosPair(i1, i2) = *swap;
 
-		let col1 = I_TO_COL[i1];
-		let col2 = I_TO_COL[i2];
+		let col1 = self.fingering[i1];
+		let col2 = self.fingering[i2];
 
 		cache.fspeed_total = if col1 == col2 {
 			let fspeed = self.col_fspeed(layout, col1);
@@ -722,13 +1581,72 @@ impl LayoutGeneration {
 		let trigrams_end = self.trigram_char_score(layout, &swap);
 		cache.trigrams_total = cache.trigrams_total - trigrams_start + trigrams_end;
 
-		if swap.affects_scissor() {
+		if self.affects_scissor(swap) {
 			cache.scissors = self.scissor_score(layout);
 		}
+		cache.lsbs = cache.lsbs - lsbs_start + self.lsb_swap_score(layout, swap);
+
+		cache.constraints = self.constraint_penalty(layout);
+
+		cache.hand_usage = self.swapped_hand_usage(layout, swap, cache.hand_usage);
+		cache.hand_balance = self.hand_balance_score(cache.hand_usage);
+
+		cache.row_usage = self.swapped_row_usage(layout, swap, cache.row_usage);
+		cache.row_use = self.row_use_score(cache.row_usage);
+
+		for (combo, cached) in layout.combos.iter().zip(cache.combos.iter_mut()) {
+			if Self::affects_combo(layout, combo, swap) {
+				*cached = self.single_combo_score(layout, combo);
+			}
+		}
+		cache.combos_total = cache.combos.iter().sum();
 
 		cache.total_score = cache.total_score();
 	}
 
+	/// The cache after swapping what combo `combo` types with the character on key `i`, which is
+	/// done on `layout`. Like a swap, that only changes what depends on the key and the combos.
+	fn swap_combo_cached(&self, layout: &mut FastLayout, combo: usize, i: usize, cache: &LayoutCache) -> LayoutCache {
+		let trigrams = self.per_char_trigrams.get(&[layout.matrix[i], layout.combos[combo].output]);
+		let trigrams_start = trigrams.map_or(0.0, |t| self.trigram_score_iter(layout, t));
+		let lsbs_start = self.lsb_swap_score(layout, &PosPair(i, i));
+
+		layout.swap_combo(combo, i);
+
+		let mut res = cache.clone();
+		let col = self.fingering[i];
+
+		let effort = self.char_effort(layout, i);
+		res.effort_total += effort - res.effort[i];
+		res.effort[i] = effort;
+
+		let usage = self.col_usage(layout, col);
+		res.usage_total += usage - res.usage[col];
+		res.usage[col] = usage;
+
+		let fspeed = self.col_fspeed(layout, col);
+		res.fspeed_total += fspeed - res.fspeed[col];
+		res.fspeed[col] = fspeed;
+
+		if self.affects_scissor(&PosPair(i, i)) {
+			res.scissors = self.scissor_score(layout);
+		}
+		res.lsbs += self.lsb_swap_score(layout, &PosPair(i, i)) - lsbs_start;
+		res.trigrams_total += trigrams.map_or(0.0, |t| self.trigram_score_iter(layout, t)) - trigrams_start;
+		res.constraints = self.constraint_penalty(layout);
+
+		res.hand_usage = self.hand_usage(layout);
+		res.hand_balance = self.hand_balance_score(res.hand_usage);
+		res.row_usage = self.row_usage(layout);
+		res.row_use = self.row_use_score(res.row_usage);
+
+		res.combos = layout.combos.iter().map(|combo| self.single_combo_score(layout, combo)).collect();
+		res.combos_total = res.combos.iter().sum();
+
+		res.total_score = res.total_score();
+		res
+	}
+
 	pub fn best_swap_cached(
 		&self, layout: &mut FastLayout, cache: &LayoutCache, current_best_score: Option<f64>, possible_swaps: &[PosPair]
 	) -> (Option<PosPair>, f64) {
@@ -766,9 +1684,11 @@ impl LayoutGeneration {
 		let mut best = layout.clone();
 		self.col_perms(layout, &mut best, cache, &mut best_score, 6);
 		layout.swap_indexes();
+		*cache = self.initialize_cache(layout);
 
 		self.col_perms(layout, &mut best, cache, &mut best_score, 6);
 		*layout = best;
+		*cache = self.initialize_cache(layout);
 		layout.score = best_score;
 	}
 
@@ -791,22 +1711,77 @@ impl LayoutGeneration {
 		for i in 0..k {
 			self.col_perms(layout, best, cache, best_score, k - 1);
 			if k % 2 == 0 {
-				self.accept_swap(layout, &PosPair(COLS[i], COLS[k - 1]), cache);
+				let swap = PosPair(self.shape.main_index(COLS[i]), self.shape.main_index(COLS[k - 1]));
+				self.accept_swap(layout, &swap, cache);
 			} else {
-				self.accept_swap(layout, &PosPair(COLS[0], COLS[k - 1]), cache);
+				let swap = PosPair(self.shape.main_index(COLS[0]), self.shape.main_index(COLS[k - 1]));
+				self.accept_swap(layout, &swap, cache);
 			}
 		}
 	}
 
+	/// A random layout of the characters to generate with, on the keyboard of the generator.
+	pub fn random_layout(&self) -> FastLayout {
+		FastLayout::random(self.chars_for_generation.clone(), self.shape, self.fingering.clone())
+	}
+
 	pub fn generate(&self) -> FastLayout {
-		let layout = FastLayout::random(self.chars_for_generation);
-		let mut cache = self.initialize_cache(&layout);
-		
-		let mut layout = self.optimize(layout, &mut cache, &POSSIBLE_SWAPS);
+		let layout = self.random_layout();
+		let mut layout = match self.optimizer {
+			Optimizer::HillClimbing => {
+				let mut cache = self.initialize_cache(&layout);
+				self.optimize(layout, &mut cache, &self.possible_swaps)
+			},
+			Optimizer::Annealing => self.anneal(layout, &self.annealing)
+		};
+		if !self.combos.is_empty() {
+			let combos = self.combos.iter().zip(&self.chars_for_combos)
+				.map(|(keys, &output)| Combo { keys: keys.clone(), output })
+				.collect();
+			layout = layout.with_combos(combos);
+			self.optimize_combos(&mut layout);
+		}
 		layout.score = self.score(&layout);
 		layout
 	}
 
+	/// Moves characters between the combos and the keys of a layout while that improves its score,
+	/// optimizing the keys again after every move. This is how rarer characters end up on combos
+	/// rather than being left out, or take the place of a key if that's better.
+	pub fn optimize_combos(&self, layout: &mut FastLayout) {
+		let mut cache = self.initialize_cache(layout);
+
+		loop {
+			let mut best_score = cache.total_score;
+			let mut best_swap = None;
+
+			for combo in 0..layout.combos.len() {
+				for i in 0..layout.matrix.len() {
+					let score = self.swap_combo_cached(layout, combo, i, &cache).total_score;
+					layout.swap_combo(combo, i);
+					if score > best_score {
+						best_score = score;
+						best_swap = Some((combo, i));
+					}
+				}
+			}
+
+			let Some((combo, i)) = best_swap else {
+				break
+			};
+			cache = self.swap_combo_cached(layout, combo, i, &cache);
+
+			// this only counts when it doesn't make the layout as a whole worse, which also makes
+			// sure this ends
+			let before = (layout.clone(), cache.clone());
+			self.optimize_mut(layout, &mut cache, &self.possible_swaps);
+			cache = self.initialize_cache(layout);
+			if cache.total_score < best_score {
+				(*layout, cache) = before;
+			}
+		}
+	}
+
 	pub fn optimize(&self, mut layout: FastLayout, cache: &mut LayoutCache, possible_swaps: &[PosPair]) -> FastLayout {
 		let mut with_col_score = f64::MIN;
 		let mut optimized_score = f64::MIN / 2.0;
@@ -817,7 +1792,6 @@ impl LayoutGeneration {
 			with_col_score = layout.score;
 		}
 
-		layout.score = optimized_score;
 		layout
 	}
 
@@ -830,8 +1804,6 @@ impl LayoutGeneration {
 			self.optimize_cols(layout, cache, Some(optimized_score));
 			with_col_score = layout.score;
 		}
-
-		layout.score = optimized_score;
 	}
 
 	pub fn generate_n_iter(&self, amount: usize) -> impl ParallelIterator<Item = FastLayout> + '_ {
@@ -841,10 +1813,29 @@ impl LayoutGeneration {
 		x
 	}
 
+	/// The symmetries that don't change the score of a layout with the fingering and key efforts
+	/// this generates with.
+	pub fn symmetries(&self) -> Vec<Symmetry> {
+		Symmetry::ALL.into_iter()
+			.filter(|s| s.check(self.shape, &self.fingering, &self.effort_map).is_ok())
+			.collect()
+	}
+
+	/// Generates `amount` layouts and collapses the ones that are the same under `symmetries`,
+	/// with how many times each was found. Fails for symmetries that change scores, see
+	/// `symmetries`.
+	pub fn generate_n_distinct(&self, amount: usize, symmetries: &[Symmetry]) -> Result<Vec<(FastLayout, usize)>> {
+		for symmetry in symmetries {
+			symmetry.check(self.shape, &self.fingering, &self.effort_map)?;
+		}
+		let layouts = self.generate_n_iter(amount).collect::<Vec<_>>();
+		Ok(distinct_layouts(layouts, symmetries, |layout| self.score(layout)))
+	}
+
 	pub fn generate_n_with_pins_iter<'a>(
 		&'a self, amount: usize, based_on: FastLayout, pins: &'a[usize]
 	) -> impl ParallelIterator<Item = FastLayout> + '_ {
-		let possible_swaps = pinned_swaps(pins);
+		let possible_swaps = pinned_swaps(pins, &self.possible_swaps);
 		
 		let x = (0..amount)
 			.into_par_iter()
@@ -857,13 +1848,15 @@ impl LayoutGeneration {
 	pub fn generate_with_pins(
 		&self, based_on: &FastLayout, pins: &[usize], possible_swaps: Option<&[PosPair]>
 	) -> FastLayout {
-		let mut layout = FastLayout::random_pins(based_on.matrix, pins);
+		let mut layout = FastLayout::random_pins(
+			based_on.matrix.clone(), pins, based_on.shape, self.fingering.clone()
+		);
 		let mut cache = self.initialize_cache(&layout);
 
 		if let Some(ps) = possible_swaps {
 			self.optimize_cached(&mut layout, &mut cache, ps)
 		} else {
-			self.optimize_cached(&mut layout, &mut cache, &pinned_swaps(pins))
+			self.optimize_cached(&mut layout, &mut cache, &pinned_swaps(pins, &self.possible_swaps))
 		};
 
 		layout.score = self.score(&layout);
@@ -872,6 +1865,8 @@ impl LayoutGeneration {
 }
 
 mod obsolete;
+pub mod annealing;
+pub mod michaelll;
 // mod iterative;
 
 #[cfg(test)]
@@ -881,6 +1876,8 @@ mod tests {
 	use std::sync::atomic::Ordering;
 use nanorand::Rng;
 	use crate::utility::ApproxEq;
+	use crate::language_data::RepeatKey;
+	use crate::keyboard::Keyboard;
 
 	lazy_static!{
 		pub static ref GEN: LayoutGeneration = LayoutGeneration::new("english", "static", None).unwrap();
@@ -888,8 +1885,9 @@ use nanorand::Rng;
 
 	#[allow(dead_code)]
 	fn fspeed_per_pair() {
-		for (pair, dist) in GEN.fspeed_vals {
-			println!("({}, {}) <-> ({}, {}): {dist}", pair.0%10, pair.0/10, pair.1%10, pair.1/10);
+		let cols = GEN.shape.cols;
+		for (pair, dist) in GEN.fspeed_vals.iter() {
+			println!("({}, {}) <-> ({}, {}): {dist}", pair.0%cols, pair.0/cols, pair.1%cols, pair.1/cols);
 		}
 	}
 
@@ -897,15 +1895,16 @@ use nanorand::Rng;
 	fn prune_heuristic_correctness() {
 		//has been tested with 10000 runs
 		let runs = 200;
+		let swap_count = GEN.possible_swaps.len();
 
 		for _ in 0..runs {
-			let mut layout = FastLayout::random(GEN.chars_for_generation);
+			let mut layout = GEN.random_layout();
 			let cache = GEN.initialize_cache(&layout);
 			
 			if let (Some(best_swap_normal), best_score_normal) =
-				GEN.best_swap(&mut layout, None, &POSSIBLE_SWAPS) &&
+				GEN.best_swap(&mut layout, None, &GEN.possible_swaps) &&
 				let (Some(best_swap_cached), best_score_cached) =
-				GEN.best_swap_cached(&mut layout, &cache, None, &POSSIBLE_SWAPS) {
+				GEN.best_swap_cached(&mut layout, &cache, None, &GEN.possible_swaps) {
 					
 				if best_score_normal.approx_eq_dbg(best_score_cached, 7) {
 					assert_eq!(best_swap_normal, best_swap_cached);
@@ -915,41 +1914,586 @@ use nanorand::Rng;
 		println!(
 			"pruned {} times.\nRecalculated trigrams {} times.\namount pruned: {:.2}%\n analyzed {} swaps",
 			PRUNED_COUNT.load(Ordering::Relaxed),
-			435 * runs - PRUNED_COUNT.load(Ordering::Relaxed),
-			(PRUNED_COUNT.load(Ordering::Relaxed) as f64) / (435.0 * runs as f64) * 100.0,
-			435 * runs
+			swap_count * runs - PRUNED_COUNT.load(Ordering::Relaxed) as usize,
+			(PRUNED_COUNT.load(Ordering::Relaxed) as f64) / ((swap_count * runs) as f64) * 100.0,
+			swap_count * runs
 		);
 	}
 
 	#[test]
 	fn cached_totals() {
+		// with the penalties that are off by default turned on
+		let mut config = Config::new();
+		config.weights.hand_balance = 1.0;
+		config.weights.max_row_use.penalty = 2.5;
+		config.weights.lsbs = 1.0;
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
 		let mut qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
-		let mut cache = GEN.initialize_cache(&qwerty);
+		let mut cache = generator.initialize_cache(&qwerty);
 		let mut rng = nanorand::tls_rng();
 
-		for swap in (0..).map(|_| &POSSIBLE_SWAPS[rng.generate_range(0..435)]).take(10000) {
-			GEN.accept_swap(&mut qwerty, swap, &mut cache);
+		for swap in (0..).map(|_| &generator.possible_swaps[rng.generate_range(0..435)]).take(10000) {
+			generator.accept_swap(&mut qwerty, swap, &mut cache);
 
-			assert!(cache.scissors.approx_eq_dbg(GEN.scissor_score(&qwerty), 7));
-			assert!(cache.effort_total.approx_eq_dbg(GEN.effort_score(&qwerty), 7));
-			assert!(cache.usage_total.approx_eq_dbg(GEN.usage_score(&qwerty), 7));
-			assert!(cache.fspeed_total.approx_eq_dbg(GEN.fspeed_score(&qwerty), 7));
+			assert!(cache.scissors.approx_eq_dbg(generator.scissor_score(&qwerty), 7));
+			assert!(cache.lsbs.approx_eq_dbg(generator.lsb_score(&qwerty), 7));
+			assert!(cache.effort_total.approx_eq_dbg(generator.effort_score(&qwerty), 7));
+			assert!(cache.usage_total.approx_eq_dbg(generator.usage_score(&qwerty), 7));
+			assert!(cache.fspeed_total.approx_eq_dbg(generator.fspeed_score(&qwerty), 7));
 			assert!(cache.trigrams_total.approx_eq_dbg(
-				GEN.trigram_score_iter(&qwerty, GEN.data.trigrams.iter().take(1000)), 7)
+				generator.trigram_score_iter(&qwerty, generator.data.trigrams.iter().take(1000)), 7)
 			);
-			assert!(cache.total_score.approx_eq_dbg(GEN.score_with_precision(&qwerty, 1000), 7));
+			assert!(cache.hand_balance.approx_eq_dbg(generator.hand_balance_score(generator.hand_usage(&qwerty)), 7));
+			assert!(cache.row_use.approx_eq_dbg(generator.row_use_score(generator.row_usage(&qwerty)), 7));
+			assert!(cache.total_score.approx_eq_dbg(generator.score_with_precision(&qwerty, 1000), 7));
 		}
 	}
 
+	#[test]
+	fn optimize_keeps_cache() {
+		let mut layout = GEN.random_layout();
+		let mut cache = GEN.initialize_cache(&layout);
+		GEN.optimize_mut(&mut layout, &mut cache, &GEN.possible_swaps);
+		let fresh = GEN.initialize_cache(&layout);
+
+		assert!(cache.hand_usage.iter().zip(fresh.hand_usage).all(|(h1, h2)| h1.approx_eq_dbg(h2, 9)));
+		assert!(cache.row_usage.iter().zip(fresh.row_usage).all(|(r1, r2)| r1.approx_eq_dbg(r2, 9)));
+		assert!(cache.total_score.approx_eq_dbg(fresh.total_score, 7));
+		assert!(layout.score.approx_eq_dbg(fresh.total_score, 7));
+	}
+
+	/// Accepts random swaps on `layout` and checks the cache still adds up to scoring it from scratch.
+	fn assert_cached_totals(generator: &LayoutGeneration, mut layout: FastLayout) -> FastLayout {
+		let mut cache = generator.initialize_cache(&layout);
+		let mut rng = nanorand::tls_rng();
+
+		for _ in 0..1000 {
+			let swap = generator.possible_swaps[rng.generate_range(0..generator.possible_swaps.len())];
+			generator.accept_swap(&mut layout, &swap, &mut cache);
+		}
+		assert!(cache.total_score.approx_eq_dbg(generator.score_with_precision(&layout, 1000), 7));
+		layout
+	}
+
+	#[test]
+	fn finger_speed_weights() {
+		let normal = LayoutGeneration::new("english", "static", Some(Config::default())).unwrap();
+		let mut config = Config::default();
+		config.weights.finger_speed.pinky *= 2.0;
+		let slow_pinky = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let normal = normal.get_layout_stats(&qwerty).finger_speed;
+		let slow_pinky = slow_pinky.get_layout_stats(&qwerty).finger_speed;
+
+		assert!(normal[0] > 0.0);
+		assert!(slow_pinky[0].approx_eq_dbg(normal[0] * 2.0, 7));
+		assert!(slow_pinky[7].approx_eq_dbg(normal[7] * 2.0, 7));
+		assert_eq!(slow_pinky[3], normal[3]);
+	}
+
+	#[test]
+	fn annealing() {
+		use annealing::{Annealing, Cooling, Restart};
+
+		let mut annealing = Annealing { start_temperature: 1.0, end_temperature: 0.01, ..Default::default() };
+		assert!(annealing.temperature(0.5).approx_eq_dbg(0.1, 7));
+		annealing.cooling = Cooling::Linear;
+		assert!(annealing.temperature(0.5).approx_eq_dbg(0.505, 7));
+		assert!(annealing.temperature(2.0).approx_eq_dbg(0.01, 7));
+
+		let annealing = Annealing { iterations: 5000, runs: 2, restart: Restart::Random, ..Default::default() };
+		let random = GEN.random_layout();
+		let layout = GEN.anneal(random.clone(), &annealing);
+		let cache = GEN.initialize_cache(&layout);
+
+		assert!(layout.score.approx_eq_dbg(cache.total_score, 7));
+		assert!(layout.score > GEN.score(&random));
+	}
+
+	#[test]
+	fn dickens() {
+		let params = michaelll::Dickens { rounds: 2, max_runs: 3, ..Default::default() };
+		let layout = GEN.optimize_dickens(&params);
+		let random = GEN.random_layout();
+
+		assert_eq!(layout.fingering, GEN.fingering);
+		assert!(layout.score.approx_eq_dbg(GEN.score(&layout), 7));
+		assert!(layout.score > GEN.score(&random));
+	}
+
+	#[test]
+	fn space_on_thumb() {
+		use crate::load_text::{TextData, TextNgrams};
+		use crate::translation::Translator;
+
+		// space data isn't shipped, so this makes a little of it
+		let text = "the quick brown fox jumps over the lazy dog, and then it sleeps in the sun. \
+			there is no other place where the fox would rather be than here with everyone else.";
+		let translator = Translator::language("english").unwrap() + Translator::new().keep_space(true).build();
+		let data = TextData::from((TextNgrams::<5>::from(text), "english", translator));
+		let base_path = std::env::temp_dir().join("oxeylyzer_space_on_thumb");
+		std::fs::create_dir_all(base_path.join("language_data")).unwrap();
+		std::fs::write(base_path.join("language_data/english_space.json"), serde_json::to_string(&data).unwrap()).unwrap();
+
+		let mut config = Config::default();
+		config.defaults.shape = LayoutShape::try_from("3x10+2").unwrap();
+		config.defaults.space = true;
+		let generator = LayoutGeneration::new("english", &base_path, Some(config)).unwrap();
+
+		let mut config = Config::default();
+		config.defaults.space = true;
+		assert!(LayoutGeneration::new("english", &base_path, Some(config)).is_err());
+		assert_eq!(generator.chars_for_generation[0], ' ');
+
+		let layout = generator.generate();
+		let space = layout.char_to_key.get(&' ').copied().unwrap();
+		assert!(generator.shape.is_thumb(space));
+		assert!(generator.data.bigrams.get(&['e', ' ']).is_some_and(|&f| f > 0.0));
+	}
+
+	#[test]
+	fn skipgram_decay() {
+		use crate::weights::SkipgramDecay;
+
+		let mut weights = Config::default().weights;
+		weights.dsfb_ratio = 0.2;
+		weights.skipgram_decay = SkipgramDecay::Exponential { decay: 0.5 };
+		assert_eq!(weights.dsfb_ratios(), [0.2, 0.1, 0.05]);
+		weights.skipgram_decay = SkipgramDecay::Power { exponent: 1.0 };
+		assert_eq!(weights.dsfb_ratios(), [0.2, 0.1, 0.2 / 3.0]);
+		weights.skipgram_decay = SkipgramDecay::Values { values: vec![0.3, 0.01] };
+		assert_eq!(weights.dsfb_ratios(), [0.3, 0.01, 0.0]);
+
+		let mut config = Config::default();
+		config.weights.skipgram_decay = SkipgramDecay::Values { values: vec![0.0; 2] };
+		let only_sfbs = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let stats = only_sfbs.get_layout_stats(&qwerty);
+		assert_eq!(stats.weighted_dsfb, 0.0);
+		assert!(stats.dsfb2 > 0.0 && stats.dsfb3 > 0.0);
+		assert!(stats.to_string().contains("Dsfb2: "));
+
+		let stats = GEN.get_layout_stats(&qwerty);
+		let [r1, r2, r3] = GEN.weights.dsfb_ratios();
+		assert!(stats.weighted_dsfb.approx_eq_dbg(stats.dsfb * r1 + stats.dsfb2 * r2 + stats.dsfb3 * r3, 7));
+	}
+
+	#[test]
+	fn lsbs() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		// e is on the middle finger top row, t on the index finger center column
+		assert!(GEN.lsb_indices.contains(&PosPair(2, 4)));
+
+		let stats = GEN.get_layout_stats(&qwerty);
+		let offenders = GEN.offenders(&qwerty, usize::MAX);
+		let total = offenders.lsbs.iter().map(|o| o.freq).sum::<f64>();
+		assert!(stats.lsbs > 0.0);
+		assert!(total.approx_eq_dbg(stats.lsbs, 7));
+		assert!(offenders.lsbs.iter().any(|o| o.ngram == "et"));
+	}
+
+	#[test]
+	fn bigger_shape_cached_totals() {
+		let mut config = Config::default();
+		config.defaults.shape = LayoutShape::new(4, 11);
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
+		assert_eq!(generator.chars_for_generation.len(), 44);
+		assert_eq!(generator.possible_swaps.len(), 44 * 43 / 2);
+
+		assert_cached_totals(&generator, generator.random_layout());
+	}
+
+	#[test]
+	fn custom_fingering_cached_totals() {
+		let mut config = Config::default();
+		// b on the right index finger
+		config.fingering = Some(parse_fingering(
+			"0 1 2 3 3 4 4 5 6 7  0 1 2 3 3 4 4 5 6 7  0 1 2 3 4 4 4 5 6 7",
+			config.defaults.shape
+		).unwrap());
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let sfb_pairs = generator.fspeed_vals.iter().map(|(pair, _)| *pair).collect::<Vec<_>>();
+
+		assert!(sfb_pairs.contains(&PosPair(5, 24)));
+		assert!(!sfb_pairs.contains(&PosPair(3, 24)));
+		assert_eq!(generator.random_layout().fingering, generator.fingering);
+
+		assert_cached_totals(&generator, generator.random_layout());
+	}
+
+	#[test]
+	fn angle_mod_fingering() {
+		let mut config = Config::default();
+		config.defaults.keyboard = Keyboard::load("ortho").unwrap();
+		let ortho = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
+		let mut config = Config::default();
+		config.defaults.keyboard = Keyboard::load("ansi angle").unwrap();
+		let angle = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
+		assert_eq!(angle.fingering[20], 1);
+		assert_eq!(ortho.fingering[20], 0);
+
+		let qwerty_str = "qwertyuiopasdfghjkl;zxcvbnm,./";
+		let qwerty_ortho = FastLayout::from((qwerty_str.chars().collect(), ortho.shape, ortho.fingering.clone()));
+		let qwerty_angle = FastLayout::try_from(qwerty_str).unwrap();
+
+		// 'ce' is an sfb on the middle finger without an angle mod, 'ct' is one on the index with it
+		let ce = angle.data.bigrams.get(&['c', 'e']).copied().unwrap_or_default();
+		let ct = angle.data.bigrams.get(&['c', 't']).copied().unwrap_or_default();
+		assert!(ce > 0.0 && ct > 0.0);
+
+		let sfb_ortho = ortho.bigram_percent(&qwerty_ortho, "sfbs");
+		let sfb_angle = angle.bigram_percent(&qwerty_angle, "sfbs");
+		assert!(!sfb_ortho.approx_equal(sfb_angle, 7));
+
+		assert_eq!(qwerty_angle.char_to_finger.get(&'c'), Some(&3));
+		let cached = angle.initialize_cache(&qwerty_angle);
+		assert!(cached.fspeed_total.approx_eq_dbg(angle.fspeed_score(&qwerty_angle), 7));
+
+		// only symmetric keyboards have symmetries that keep the score
+		assert_eq!(ortho.symmetries(), [Symmetry::Mirror]);
+		assert!(angle.symmetries().is_empty());
+		assert!(angle.generate_n_distinct(1, &[Symmetry::Mirror]).is_err());
+		assert!(angle.generate_n_distinct(1, &[Symmetry::Custom(vec![0, 1])]).is_err());
+		let canonical = qwerty_ortho.canonical(&ortho.symmetries());
+		assert_ne!(canonical.matrix, qwerty_ortho.matrix);
+		assert!(ortho.score(&canonical).approx_eq_dbg(ortho.score(&qwerty_ortho), 7));
+	}
+
+	#[test]
+	fn layered_cached_totals() {
+		let layers = layers_from_kb(
+			"layer sym ^\n_ _ _ _ _  _ _ _ _ _\n_ _ _ _ _  / _ _ _ _\n_ _ _ _ _  _ _ _ _ _",
+			GEN.shape
+		).unwrap();
+		let layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.^").unwrap()
+			.with_layers(layers);
+		let generator = GEN.with_layers(&layout).unwrap();
+
+		let slash = GEN.data.characters.get(&'/').copied().unwrap_or_default();
+		assert!(slash > 0.0);
+		assert_eq!(generator.data.characters.get(&'/'), None);
+		assert!(generator.data.characters.get(&'^').unwrap().approx_eq_dbg(slash / (1.0 + slash), 9));
+
+		// the layer character moves along with the key it's on
+		let layout = assert_cached_totals(&generator, layout);
+		let h = layout.char_to_key.get(&'h').copied().unwrap();
+		assert_eq!(layout.layers[0].matrix[h], '/');
+		assert_eq!(layout.layer_keys().get(&'/'), Some(&['^', 'h']));
+	}
+
+	#[test]
+	fn shift_layer() {
+		use crate::load_text::{TextData, TextNgrams};
+		use crate::translation::Translator;
+
+		let layers = layers_from_kb(
+			"layer shift ^\nQ W E R T  Y U I O P\nA S D F G  H J K L _\nZ X C V B  N M _ _ _",
+			GEN.shape
+		).unwrap();
+		let layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.^").unwrap()
+			.with_layers(layers);
+		// the shipped data has no uppercase letters to type with shift
+		assert!(GEN.with_layers(&layout).is_err());
+
+		let text = "The Quick Brown Fox Jumps Over The Lazy Dog, Then It Sleeps In The Sun All Day.";
+		let data = TextData::from((TextNgrams::<5>::from(text), "shifted", Translator::raw(false)));
+		let base_path = std::env::temp_dir().join("oxeylyzer_shift_layer");
+		std::fs::create_dir_all(base_path.join("language_data")).unwrap();
+		std::fs::write(base_path.join("language_data/shifted.json"), serde_json::to_string(&data).unwrap()).unwrap();
+
+		let generator = LayoutGeneration::new("shifted", &base_path, Some(Config::default())).unwrap();
+		let shifted = generator.with_layers(&layout).unwrap();
+		assert!(shifted.data.characters.get(&'^').is_some_and(|&f| f > 0.0));
+
+		let before = generator.get_layout_stats(&layout);
+		let after = shifted.get_layout_stats(&layout);
+		assert!(!after.hand_balance.approx_equal(before.hand_balance, 9));
+	}
+
+	#[test]
+	fn repeat_key() {
+		let mut config = Config::default();
+		config.repeat_key = Some(RepeatKey::new('@'));
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+
+		assert!(generator.chars_for_generation.contains(&'@'));
+		assert_eq!(generator.chars_for_generation.len(), GEN.chars_for_generation.len());
+		assert!(generator.data.characters.get(&'@').unwrap() > &0.0);
+
+		let layout = generator.random_layout();
+		let doubled = GEN.data.bigrams.iter()
+			.filter(|([c1, c2], _)| c1 == c2 && *c1 != ' ')
+			.map(|(_, f)| f)
+			.sum::<f64>();
+
+		let repeat = generator.get_layout_stats(&layout).repeat.unwrap();
+		assert!(doubled > 0.0);
+		assert!(repeat.same_key.approx_eq_dbg(doubled, 9));
+		assert_eq!(repeat.sfbs, 0.0);
+		assert!(GEN.get_layout_stats(&layout).repeat.is_none());
+	}
+
+	#[test]
+	fn combos() {
+		let mut layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap()
+			.with_combos(vec![Combo { keys: vec![21, 22], output: '\'' }]);
+		let stats = GEN.get_layout_stats(&layout).combos.unwrap();
+		assert!(stats.presses.approx_eq_dbg(GEN.data.characters.get(&'\'').copied().unwrap(), 9));
+		assert!(GEN.combo_score(&layout) > 0.0);
+
+		// the cache keeps up with swaps and with moving characters between the combo and the keys
+		let mut cached = assert_cached_totals(&GEN, layout.clone());
+		let mut cache = GEN.initialize_cache(&cached);
+		for i in (0..cached.matrix.len()).step_by(7) {
+			cache = GEN.swap_combo_cached(&mut cached, 0, i, &cache);
+
+			assert!(cache.combos_total.approx_eq_dbg(GEN.combo_score(&cached), 7));
+			assert!(cache.total_score.approx_eq_dbg(GEN.score_with_precision(&cached, 1000), 7));
+		}
+
+		// e is too common for a combo, so it goes back to a key
+		layout.swap_combo(0, 2);
+		let score = GEN.score(&layout);
+		GEN.optimize_combos(&mut layout);
+		assert_ne!(layout.combos[0].output, 'e');
+		assert!(GEN.score(&layout) > score);
+
+		let mut config = Config::default();
+		config.combos = vec![vec![21, 22], vec![22, 23]];
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		assert_eq!(generator.chars_for_combos.len(), 2);
+
+		let layout = generator.generate();
+		let mut typed = layout.matrix.iter()
+			.chain(layout.combos.iter().map(|c| &c.output))
+			.collect::<Vec<_>>();
+		let mut available = generator.chars_for_generation.iter()
+			.chain(&generator.chars_for_combos)
+			.collect::<Vec<_>>();
+		typed.sort();
+		available.sort();
+		assert_eq!(typed, available);
+		assert!(layout.score.approx_eq_dbg(generator.score(&layout), 7));
+
+		let mut config = Config::default();
+		config.combos = vec![vec![21, 30]];
+		assert!(LayoutGeneration::new("english", "static", Some(config)).is_err());
+	}
+
+	#[test]
+	fn constraints() {
+		use crate::constraints::{Constraint, Rule};
+
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+
+		let mut config = Config::default();
+		config.constraints = vec![
+			Constraint::soft(Rule::Fingers(vec!['e'], vec![0, 7]), 0.5),
+			Constraint::soft(Rule::DifferentHands('a', 'e'), 0.25),
+			Constraint::soft(Rule::SameHand(vec!['n', 'e']), 0.125)
+		];
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let plain = LayoutGeneration::new("english", "static", Some(Config::default())).unwrap();
+		let penalty = generator.score(&qwerty) - plain.score(&qwerty);
+
+		assert!(penalty.approx_eq_dbg(-0.875, 7));
+		assert_eq!(generator.get_layout_stats(&qwerty).broken_constraints.len(), 3);
+		assert!(generator.score_breakdown(&qwerty).total.constraints.approx_eq_dbg(-0.875, 7));
+		let mut cache = generator.initialize_cache(&qwerty);
+		assert!(cache.total_score.approx_eq_dbg(generator.score_with_precision(&qwerty, 1000), 7));
+
+		let mut swapped = qwerty.clone();
+		generator.accept_swap(&mut swapped, &PosPair(2, 10), &mut cache);
+		assert!(cache.total_score.approx_eq_dbg(generator.score_with_precision(&swapped, 1000), 7));
+		assert_eq!(generator.broken_constraints(&swapped), generator.constraints[1..]);
+
+		let mut config = Config::default();
+		config.constraints = vec![Constraint::hard(Rule::Fingers(vec!['e'], vec![0, 7]))];
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let layout = generator.generate();
+
+		assert!(matches!(layout.char_to_finger.get(&'e'), Some(0 | 7)));
+		assert!(generator.get_layout_stats(&layout).broken_constraints.is_empty());
+	}
+
+	#[test]
+	fn hand_balance() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let [left, right] = GEN.hand_usage(&qwerty);
+		let stats = GEN.get_layout_stats(&qwerty);
+
+		assert!(stats.hand_balance > 0.55);
+		assert!(stats.hand_balance.approx_eq_dbg(left / (left + right), 9));
+		assert!(GEN.score_breakdown(&qwerty).total.hand_balance.approx_eq_dbg(
+			-GEN.weights.hand_balance * (left - right), 9
+		));
+
+		let mut config = Config::default();
+		config.weights.hand_balance = 50.0;
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let penalty = |layout: &FastLayout| {
+			let [left, right] = generator.hand_usage(layout);
+			50.0 * (left - right).abs()
+		};
+		let mut layout = qwerty.clone();
+		let mut cache = generator.initialize_cache(&layout);
+
+		// e to the right hand and i to the left evens out the hands, q and w stay on the left
+		for (swap, balances) in [(PosPair(2, 7), true), (PosPair(0, 1), false)] {
+			let before = cache.hand_balance;
+			let predicted = generator.score_swap_cached(&mut layout, &swap, &cache);
+			generator.accept_swap(&mut layout, &swap, &mut cache);
+
+			assert!(predicted.approx_eq_dbg(cache.total_score, 7));
+			assert!(cache.hand_balance.approx_eq_dbg(penalty(&layout), 7));
+			assert_eq!(cache.hand_balance < before - 1.0, balances);
+		}
+	}
+
+	#[test]
+	fn rows() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let rows = GEN.get_layout_stats(&qwerty).rows;
+
+		assert!((rows.top.total() + rows.home.total() + rows.bottom.total() - 1.0).abs() < 1e-9);
+		assert!(rows.top.total() > rows.home.total());
+		assert!(rows.top.left > rows.top.right);
+		// like "ex" and "cr" on qwerty
+		assert!(rows.row_jumps > 0.0);
+
+		let [top, _, bottom] = GEN.row_usage(&qwerty);
+		let max = &GEN.weights.max_row_use;
+		let penalty = max.penalty * ((top - max.top).max(0.0) + (bottom - max.bottom).max(0.0));
+		assert!(GEN.score_breakdown(&qwerty).total.row_use.approx_eq_dbg(-penalty, 9));
+
+		let mut config = Config::default();
+		config.weights.max_row_use.top = 0.1;
+		config.weights.max_row_use.bottom = 1.0;
+		config.weights.max_row_use.penalty = 50.0;
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let mut layout = qwerty.clone();
+		let mut cache = generator.initialize_cache(&layout);
+
+		// e to the home row and h to the top row takes load off the top row, q and w stay on it
+		for (swap, lightens) in [(PosPair(2, 15), true), (PosPair(0, 1), false)] {
+			let before = cache.row_use;
+			let predicted = generator.score_swap_cached(&mut layout, &swap, &cache);
+			generator.accept_swap(&mut layout, &swap, &mut cache);
+
+			assert!(predicted.approx_eq_dbg(cache.total_score, 7));
+			assert!(cache.row_use.approx_eq_dbg(generator.row_use_score(generator.row_usage(&layout)), 7));
+			assert!(cache.row_use.approx_eq_dbg(50.0 * (generator.row_usage(&layout)[0] - 0.1), 7));
+			assert_eq!(cache.row_use < before - 1.0, lightens);
+		}
+	}
+
+	#[test]
+	fn score_breakdown() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let breakdown = GEN.score_breakdown(&qwerty);
+		let total = breakdown.total;
+
+		assert!(GEN.score(&qwerty) < 0.0);
+		assert!(total.total().approx_eq_dbg(GEN.score(&qwerty), 7));
+		assert!(total.effort.approx_eq_dbg(-(0..30).map(|i| GEN.char_effort(&qwerty, i)).sum::<f64>(), 7));
+		assert!(total.scissors.approx_eq_dbg(-GEN.scissor_score(&qwerty), 7));
+		assert!(total.trigrams.approx_eq_dbg(GEN.trigram_score_iter(&qwerty, &GEN.data.trigrams), 7));
+		assert_eq!(breakdown.keys.len(), 30);
+
+		// moving e to a pinky makes e cost more effort and fspeed, while the key left for it gets off easier
+		let mut swapped = qwerty.clone();
+		swapped.swap(2, 20);
+		let diff = GEN.score_breakdown(&swapped).char('e').unwrap() - breakdown.char('e').unwrap();
+		assert!(diff.effort < 0.0);
+		assert!(diff.total() < 0.0);
+		assert!(breakdown.char('e').unwrap().total() < breakdown.char('q').unwrap().total());
+		assert_eq!(breakdown.char('é'), None);
+	}
+
+	#[test]
+	fn offenders() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let offenders = GEN.offenders(&qwerty, 5);
+
+		assert_eq!(offenders.sfbs.len(), 5);
+		assert!(offenders.sfbs.windows(2).all(|w| w[0].freq >= w[1].freq));
+		assert!(offenders.sfbs.iter().any(|o| o.ngram == "ed" || o.ngram == "de"));
+		assert!(offenders.sfbs[0].share > 0.0 && offenders.sfbs[0].share <= 1.0);
+
+		let sfb = GEN.bigram_percent(&qwerty, "sfbs");
+		assert!((offenders.sfbs[0].freq / sfb).approx_eq_dbg(offenders.sfbs[0].share, 9));
+
+		assert_eq!(offenders.trigrams.len(), GEN.trigram_taxonomy.classes.len());
+		let inrolls = GEN.trigram_stats(&qwerty, usize::MAX).get("Inrolls");
+		let top = &offenders.trigrams["Inrolls"][0];
+		assert!((top.freq / inrolls).approx_eq_dbg(top.share, 9));
+		assert_eq!(GEN.offenders(&qwerty, 0).sfbs.len(), 0);
+	}
+
+	#[test]
+	fn trigram_classes() {
+		use crate::trigram_patterns::{TrigramClass, TrigramTaxonomy};
+
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let stats = GEN.trigram_stats(&qwerty, usize::MAX);
+		let total = stats.classes.values().sum::<f64>() + stats.other + stats.invalid;
+		let trigrams = GEN.data.trigrams.iter().map(|(_, f)| f).sum::<f64>();
+
+		assert_eq!(stats.classes.len(), GEN.trigram_taxonomy.classes.len());
+		assert!(total.approx_eq_dbg(trigrams, 9));
+		assert!(stats.get("Alternates") > stats.get("Bad Redirects"));
+
+		let rolls = stats.total(&["Inrolls", "Outrolls"]);
+		let shown = stats.to_string();
+		assert!(shown.contains(&format!("Outrolls: {:.3}%\nTotal Rolls: {:.3}%", stats.get("Outrolls") * 100.0, rolls * 100.0)));
+		assert!(shown.contains("\nTotal Alternates: ") && shown.contains("\nTotal Redirects: "));
+
+		let mut config = Config::default();
+		config.trigram_classes = Some(TrigramTaxonomy::new(vec![
+			TrigramClass::new("Same row inrolls", 1.0, &[
+				"h1 == h2 && h2 != h3 && f1 < f2 && r1 == r2", "h1 != h2 && h2 == h3 && f2 < f3 && r2 == r3"
+			]).unwrap()
+		]).unwrap());
+		let generator = LayoutGeneration::new("english", "static", Some(config)).unwrap();
+		let stats = generator.trigram_stats(&qwerty, usize::MAX);
+		let score = generator.trigram_score_iter(&qwerty, &generator.data.trigrams);
+
+		assert_eq!(stats.classes.keys().collect::<Vec<_>>(), vec!["Same row inrolls"]);
+		assert!(score.approx_eq_dbg(stats.get("Same row inrolls"), 9));
+		assert!(stats.get("Same row inrolls") < GEN.trigram_stats(&qwerty, usize::MAX).get("Inrolls"));
+		assert!(!stats.to_string().contains("Total"));
+	}
+
+	#[test]
+	fn swap_path() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let colemak = FastLayout::try_from("qwfpgjluy;arstdhneiozxcvbkm,./").unwrap();
+
+		let path = GEN.swap_path(&qwerty, &colemak).unwrap();
+		assert_eq!(path.len(), qwerty.swaps_to(&colemak).unwrap().len());
+
+		let total_change = path.iter().map(|step| step.score_change).sum::<f64>();
+		assert!(total_change.approx_eq_dbg(GEN.score(&colemak) - GEN.score(&qwerty), 7));
+
+		let last = path.last().unwrap();
+		assert!(last.score.approx_eq_dbg(GEN.score(&colemak), 7));
+		assert!(last.stats.sfb.approx_eq_dbg(GEN.bigram_percent(&colemak, "sfbs"), 7));
+	}
+
 	#[test]
 	fn best_found_swap() {
 		let mut qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
 		let cache = GEN.initialize_cache(&qwerty);
 		
 		if let (Some(best_swap_normal), best_score_normal) =
-			GEN.best_swap(&mut qwerty, None, &POSSIBLE_SWAPS) &&
+			GEN.best_swap(&mut qwerty, None, &GEN.possible_swaps) &&
 			let (Some(best_swap_cached), best_score_cached) =
-			GEN.best_swap_cached(&mut qwerty, &cache, None, &POSSIBLE_SWAPS) {
+			GEN.best_swap_cached(&mut qwerty, &cache, None, &GEN.possible_swaps) {
 				
 			if best_score_normal.approx_eq_dbg(best_score_cached, 7) {
 				assert_eq!(best_swap_normal, best_swap_cached);
@@ -964,7 +2508,7 @@ use nanorand::Rng;
 		let mut qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
 		let mut cache = GEN.initialize_cache(&qwerty);
 
-		for swap in POSSIBLE_SWAPS.iter() {
+		for swap in GEN.possible_swaps.iter() {
 			let score_normal = GEN.score_swap(&mut qwerty, swap);
 			let score_cached = GEN.score_swap_cached(&mut qwerty, swap, &mut cache);
 		
@@ -979,14 +2523,14 @@ use nanorand::Rng;
 		let qwerty = FastLayout::try_from(qwerty_str).unwrap();
 
 		let optimized_normal = 
-			GEN.optimize_normal_no_cols(qwerty.clone(), &POSSIBLE_SWAPS);
+			GEN.optimize_normal_no_cols(qwerty.clone(), &GEN.possible_swaps);
 		let normal_score = GEN.score_with_precision(&optimized_normal, 1000);
 
 		let mut qwerty_for_cached = FastLayout::try_from(qwerty_str).unwrap();
 		let mut cache = GEN.initialize_cache(&qwerty_for_cached);
 
 		let best_cached_score =
-			GEN.optimize_cached(&mut qwerty_for_cached, &mut cache, &POSSIBLE_SWAPS);
+			GEN.optimize_cached(&mut qwerty_for_cached, &mut cache, &GEN.possible_swaps);
 
 		assert!(normal_score.approx_eq_dbg(best_cached_score, 7));
 		assert_eq!(qwerty_for_cached.layout_str(), optimized_normal.layout_str());
@@ -996,16 +2540,16 @@ use nanorand::Rng;
 	#[test]
 	fn optimize_random_layouts() {
 		for _ in 0..5 {
-			let layout = FastLayout::random(GEN.chars_for_generation);
+			let layout = GEN.random_layout();
 			let mut layout_for_cached = layout.clone();
 
 			let optimized_normal = 
-				GEN.optimize_normal_no_cols(layout, &POSSIBLE_SWAPS);
+				GEN.optimize_normal_no_cols(layout, &GEN.possible_swaps);
 			let normal_score = GEN.score_with_precision(&optimized_normal, 1000);
 
 			let mut cache = GEN.initialize_cache(&layout_for_cached);
 			let best_cached_score =
-				GEN.optimize_cached(&mut layout_for_cached, &mut cache, &POSSIBLE_SWAPS);
+				GEN.optimize_cached(&mut layout_for_cached, &mut cache, &GEN.possible_swaps);
 
 			assert!(normal_score.approx_eq_dbg(best_cached_score, 7));
 			assert_eq!(layout_for_cached.layout_str(), optimized_normal.layout_str());
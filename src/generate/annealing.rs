@@ -0,0 +1,137 @@
+use nanorand::{Rng, tls_rng};
+use serde::Deserialize;
+
+use crate::generate::{LayoutCache, LayoutGeneration};
+use crate::layout::*;
+use crate::utility::*;
+
+/// How layouts are generated.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Optimizer {
+	/// Takes the best swap until there's none left, then tries column permutations.
+	#[default]
+	#[serde(rename = "hill climbing")]
+	HillClimbing,
+	/// Simulated annealing, see `Annealing`.
+	#[serde(rename = "annealing")]
+	Annealing
+}
+
+/// How the temperature goes from the start temperature to the end temperature over a run.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Cooling {
+	/// Multiplied by the same factor every iteration.
+	#[default]
+	Exponential,
+	/// Lowered by the same amount every iteration.
+	Linear
+}
+
+/// Where every run after the first starts.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Restart {
+	/// From the best layout found so far, heated up again.
+	#[default]
+	Best,
+	/// From a new random layout.
+	Random
+}
+
+/// Settings of simulated annealing. Swaps that make a layout worse are taken with a chance of
+/// e^(score change / temperature), so a higher temperature lets a run climb out of local optima.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Annealing {
+	/// Temperature at the start of every run, in score points.
+	pub start_temperature: f64,
+	/// Temperature at the end of every run.
+	pub end_temperature: f64,
+	pub cooling: Cooling,
+	/// Random swaps tried per run.
+	pub iterations: usize,
+	/// How many runs there are, the best layout of all of them is kept.
+	pub runs: usize,
+	pub restart: Restart
+}
+
+impl Default for Annealing {
+	fn default() -> Self {
+		Self {
+			start_temperature: 0.2,
+			end_temperature: 0.001,
+			cooling: Cooling::Exponential,
+			iterations: 100_000,
+			runs: 2,
+			restart: Restart::Best
+		}
+	}
+}
+
+impl Annealing {
+	/// The temperature after `progress` of a run, going from 0 to 1.
+	pub fn temperature(&self, progress: f64) -> f64 {
+		let progress = progress.clamp(0.0, 1.0);
+		match self.cooling {
+			Cooling::Exponential => {
+				self.start_temperature * (self.end_temperature / self.start_temperature).powf(progress)
+			},
+			Cooling::Linear => {
+				self.start_temperature + (self.end_temperature - self.start_temperature) * progress
+			}
+		}
+	}
+}
+
+impl LayoutGeneration {
+	/// One run of simulated annealing from a layout, keeping track of the best layout it passes.
+	fn anneal_run(
+		&self,
+		layout: &mut FastLayout,
+		cache: &mut LayoutCache,
+		possible_swaps: &[PosPair],
+		annealing: &Annealing,
+		best: &mut FastLayout
+	) {
+		let mut rng = tls_rng();
+
+		for i in 0..annealing.iterations {
+			let temperature = annealing.temperature(i as f64 / annealing.iterations as f64);
+			let swap = &possible_swaps[rng.generate_range(0..possible_swaps.len())];
+			let change = self.score_swap_cached(layout, swap, cache) - cache.total_score;
+
+			if change >= 0.0 || rng.generate::<f64>() < (change / temperature).exp() {
+				self.accept_swap(layout, swap, cache);
+
+				if cache.total_score > best.score {
+					*best = layout.clone();
+					best.score = cache.total_score;
+				}
+			}
+		}
+	}
+
+	/// Optimizes a layout with simulated annealing, then climbs to the nearest local optimum of the
+	/// best layout it found.
+	pub fn anneal(&self, mut layout: FastLayout, annealing: &Annealing) -> FastLayout {
+		let possible_swaps = &self.possible_swaps;
+		let mut cache = self.initialize_cache(&layout);
+		let mut best = layout.clone();
+		best.score = cache.total_score;
+
+		for run in 0..annealing.runs.max(1) {
+			if run > 0 {
+				layout = match annealing.restart {
+					Restart::Best => best.clone(),
+					Restart::Random => self.random_layout()
+				};
+				cache = self.initialize_cache(&layout);
+			}
+			self.anneal_run(&mut layout, &mut cache, possible_swaps, annealing, &mut best);
+		}
+
+		let mut cache = self.initialize_cache(&best);
+		self.optimize(best, &mut cache, possible_swaps)
+	}
+}
@@ -1,6 +1,8 @@
+use nanorand::{Rng, tls_rng};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
 use crate::{
     generate::LayoutGeneration,
-    utility::*,
     layout::*
 };
 
@@ -13,30 +15,147 @@ const GTB_ROUNDS: usize = 1;
 const MAX_RUNS: usize = usize::MAX;
 const CHANCE_EXPONENTIATOR: f64 = 0.9;
 
+/// Tuning of the iterated search of Michael Dickens' keyboard optimizer. Every run optimizes
+/// `rounds` layouts in parallel, each a perturbed copy of the best layout so far or a new random one,
+/// and the search stops once `gtb_rounds` runs in a row didn't beat the best layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dickens {
+    /// Layouts optimized per run.
+    pub rounds: usize,
+    /// Chance a round starts from the best layout so far rather than a random one.
+    pub chance_to_use_previous_layout: f64,
+    /// Every this many runs, the chance to not use the best layout is multiplied by
+    /// `chance_exponentiator`.
+    pub runs_before_chance_inc: usize,
+    pub chance_exponentiator: f64,
+    /// Random swaps made to the best layout before optimizing it, `None` for a 15th of the keys.
+    pub swaps: Option<usize>,
+    /// Every this many runs, one more swap is made.
+    pub runs_before_swaps_inc: usize,
+    /// Runs in a row without a better layout before the search stops.
+    pub gtb_rounds: usize,
+    /// Every this many runs, one more run without a better layout is allowed.
+    pub runs_before_gtb_rounds_inc: usize,
+    pub max_runs: usize
+}
+
+impl Default for Dickens {
+    fn default() -> Self {
+        Self {
+            rounds: ALGORITHM_ROUNDS,
+            chance_to_use_previous_layout: CHANCE_TO_USE_PREVIOUS_LAYOUT,
+            runs_before_chance_inc: RUNS_BEFORE_CHANCE_INC,
+            chance_exponentiator: CHANCE_EXPONENTIATOR,
+            swaps: None,
+            runs_before_swaps_inc: RUNS_BEFORE_SWAPS_INC,
+            gtb_rounds: GTB_ROUNDS,
+            runs_before_gtb_rounds_inc: RUNS_BEFORE_GTB_ROUNDS_INC,
+            max_runs: MAX_RUNS
+        }
+    }
+}
+
+/// State of the search, shared by the threads of a run.
 struct ThreadArg {
     bestk: FastLayout,
     num_rounds: usize,
     chance_to_use_previous_layout: f64,
     number_of_swaps: usize,
+    gtb_rounds: usize,
     num_threads: usize,
     is_finished: bool
 }
 
 impl ThreadArg {
-    pub fn new(available_chars: [char; 30]) -> Self {
+    pub fn new(generator: &LayoutGeneration, params: &Dickens) -> Self {
+        let available_chars = &generator.chars_for_generation;
+        let num_threads = rayon::current_num_threads().max(1);
+
         ThreadArg {
-            bestk: FastLayout::random(available_chars),
-            num_rounds: ALGORITHM_ROUNDS,
-            chance_to_use_previous_layout: CHANCE_TO_USE_PREVIOUS_LAYOUT,
-            number_of_swaps: available_chars.len() / 15,
-            num_threads: 0,
+            bestk: generator.random_layout(),
+            num_rounds: params.rounds.max(1),
+            chance_to_use_previous_layout: params.chance_to_use_previous_layout,
+            number_of_swaps: params.swaps.unwrap_or(available_chars.len() / 15).max(1),
+            gtb_rounds: params.gtb_rounds.max(1),
+            num_threads,
             is_finished: false
         }
     }
+
+    /// Makes the best layout more likely to be used and perturbed more, and allows more runs
+    /// without improvement, at the intervals of `params`.
+    fn next_run(&mut self, run: usize, params: &Dickens, max_swaps: usize) {
+        let every = |runs: usize| runs > 0 && run.is_multiple_of(runs);
+
+        if every(params.runs_before_chance_inc) {
+            self.chance_to_use_previous_layout =
+                1.0 - (1.0 - self.chance_to_use_previous_layout) * params.chance_exponentiator;
+        }
+        if every(params.runs_before_swaps_inc) {
+            self.number_of_swaps = (self.number_of_swaps + 1).min(max_swaps);
+        }
+        if every(params.runs_before_gtb_rounds_inc) {
+            self.gtb_rounds += 1;
+        }
+    }
 }
 
 impl LayoutGeneration {
-    pub fn optimize_dickens(&self, possible_swaps: &[PosPair]) {
-		
-	}
-}
\ No newline at end of file
+    /// A layout to optimize in a round, the best layout so far with some random swaps or a new one.
+    fn dickens_start(&self, arg: &ThreadArg) -> FastLayout {
+        let mut rng = tls_rng();
+
+        if rng.generate::<f64>() < arg.chance_to_use_previous_layout {
+            let mut layout = arg.bestk.clone();
+            for _ in 0..arg.number_of_swaps {
+                let swap = &self.possible_swaps[rng.generate_range(0..self.possible_swaps.len())];
+                layout.swap_pair(swap);
+            }
+            layout
+        } else {
+            self.random_layout()
+        }
+    }
+
+    /// Iterated local search after Michael Dickens' keyboard optimizer. Every run hill climbs a
+    /// number of layouts on all threads, reusing the best one with a growing chance and perturbing
+    /// it with more swaps every time, until enough runs in a row didn't find a better layout.
+    pub fn optimize_dickens(&self, params: &Dickens) -> FastLayout {
+        let mut arg = ThreadArg::new(self, params);
+        let max_swaps = self.chars_for_generation.len().max(1);
+        let mut runs_without_improvement = 0;
+        let mut run = 0;
+
+        while !arg.is_finished && run < params.max_runs {
+            // the first threads run one more round for the rounds that don't split evenly
+            let rounds = |thread: usize| {
+                arg.num_rounds / arg.num_threads + usize::from(thread < arg.num_rounds % arg.num_threads)
+            };
+            let shared = &arg;
+            let best_of_run = (0..arg.num_threads)
+                .into_par_iter()
+                .flat_map_iter(|thread| (0..rounds(thread)).map(move |_| {
+                    let layout = self.dickens_start(shared);
+                    let mut cache = self.initialize_cache(&layout);
+                    self.optimize(layout, &mut cache, &self.possible_swaps)
+                }))
+                .reduce_with(|l1, l2| if l1.score >= l2.score { l1 } else { l2 });
+
+            match best_of_run {
+                Some(layout) if run == 0 || layout.score > arg.bestk.score => {
+                    arg.bestk = layout;
+                    runs_without_improvement = 0;
+                },
+                _ => runs_without_improvement += 1
+            }
+            run += 1;
+
+            arg.is_finished = runs_without_improvement >= arg.gtb_rounds;
+            arg.next_run(run, params, max_swaps);
+        }
+
+        let mut best = arg.bestk;
+        best.score = self.score(&best);
+        best
+    }
+}
@@ -12,27 +12,31 @@ impl LayoutGeneration {
             .map(|i| self.char_effort(layout, i))
             .sum::<f64>();
         
-        let fspeed_usage = (0..8)
+        let fspeed_usage = (0..FINGER_COUNT)
             .into_iter()
             .map(|col| self.col_usage(layout, col) + self.col_fspeed(layout, col))
             .sum::<f64>();
 
         let scissors = self.scissor_score(layout);
+        let lsbs = self.lsb_score(layout);
         let trigram_iter =
             self.data.trigrams.iter().take(trigram_precision);
         let trigram_score = self.trigram_score_iter(layout, trigram_iter);
 
-        trigram_score - effort - fspeed_usage - scissors
+        let constraints = self.constraint_penalty(layout);
+        let hand_balance = self.hand_balance_score(self.hand_usage(layout));
+        let row_use = self.row_use_score(self.row_usage(layout));
+        let combos = self.combo_score(layout);
+
+        trigram_score - effort - fspeed_usage - scissors - lsbs - constraints - hand_balance - row_use - combos
     }
 
     #[allow(dead_code)]
     fn col_fspeed_before(&self, layout: &FastLayout, col: usize) -> f64 {
-		let (start, len) = unsafe { Self::col_to_start_len(col) };
+		let (start, len) = unsafe { self.col_to_start_len(col) };
 
 		let mut res = 0.0;
-		let dsfb_ratio = self.weights.dsfb_ratio;
-		let dsfb_ratio2 = self.weights.dsfb_ratio2;
-		let dsfb_ratio3 = self.weights.dsfb_ratio3;
+		let dsfb_ratios = self.weights.dsfb_ratios();
 
 		for i in start..(start+len) {
 			let (PosPair(i1, i2), dist) = self.fspeed_vals[i];
@@ -45,14 +49,10 @@ impl LayoutGeneration {
 			res += self.data.bigrams.get(&pair).unwrap_or_else(|| &0.0) * dist;
 			res += self.data.bigrams.get(&rev).unwrap_or_else(|| &0.0) * dist;
 
-			res += self.data.skipgrams.get(&pair).unwrap_or_else(|| &0.0) * dist * dsfb_ratio;
-			res += self.data.skipgrams.get(&rev).unwrap_or_else(|| &0.0) * dist * dsfb_ratio;
-
-			res += self.data.skipgrams2.get(&pair).unwrap_or_else(|| &0.0) * dist * dsfb_ratio2;
-			res += self.data.skipgrams2.get(&rev).unwrap_or_else(|| &0.0) * dist * dsfb_ratio2;
-
-			res += self.data.skipgrams3.get(&pair).unwrap_or_else(|| &0.0) * dist * dsfb_ratio3;
-			res += self.data.skipgrams3.get(&rev).unwrap_or_else(|| &0.0) * dist * dsfb_ratio3;
+			for (skipgrams, ratio) in self.data.skipgrams().into_iter().zip(dsfb_ratios) {
+				res += skipgrams.get(&pair).unwrap_or_else(|| &0.0) * dist * ratio;
+				res += skipgrams.get(&rev).unwrap_or_else(|| &0.0) * dist * ratio;
+			}
 		}
 
 		res * self.weights.fspeed
@@ -105,11 +105,11 @@ impl LayoutGeneration {
 
     #[allow(dead_code)]
     pub(crate) fn usage_score(&self, layout: &FastLayout) -> f64 {
-        (0..8).map(|i| self.col_usage(layout, i)).sum()
+        (0..FINGER_COUNT).map(|i| self.col_usage(layout, i)).sum()
     }
 
     #[allow(dead_code)]
     pub(crate) fn fspeed_score(&self, layout: &FastLayout) -> f64 {
-        (0..8).map(|i| self.col_fspeed(layout, i)).sum()
+        (0..FINGER_COUNT).map(|i| self.col_fspeed(layout, i)).sum()
     }
 }
\ No newline at end of file
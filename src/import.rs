@@ -0,0 +1,303 @@
+use std::path::Path;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::export::{PhysicalKey, PHYSICAL_KEYS, KEYSYM_NAMES};
+use crate::layout::FastLayout;
+
+/// The qwerty keys of the 30 key matrix, which is where imported layouts end up.
+const QWERTY: &str = "qwertyuiopasdfghjkl;zxcvbnm,./";
+
+/// A layout built from another format, along with the keys of that format it couldn't place on
+/// the 30 key matrix.
+#[derive(Clone)]
+pub struct Imported {
+	pub layout: FastLayout,
+	pub unmapped: Vec<String>
+}
+
+/// Whether `path` is in a format one of the importers reads, going by its extension.
+pub fn is_importable<P: AsRef<Path>>(path: P) -> bool {
+	matches!(path.as_ref().extension().and_then(|e| e.to_str()), Some("json" | "xkb" | "klc"))
+}
+
+/// Imports a `.json` keyboard-layout-editor file, an `.xkb` symbols file or a `.klc` file.
+pub fn import_file<P: AsRef<Path>>(path: P) -> Result<Imported> {
+	let content = std::fs::read_to_string(&path)?;
+
+	match path.as_ref().extension().and_then(|e| e.to_str()) {
+		Some("json") => from_kle(&content),
+		Some("xkb") => from_xkb(&content),
+		Some("klc") => from_klc(&content),
+		_ => anyhow::bail!("{} isn't a file that can be imported", path.as_ref().display())
+	}
+}
+
+/// The lowercase version of a single character, following the same rule as
+/// `Translator::letter_to_lowercase`.
+fn lowercase(c: char) -> char {
+	let mut lower = c.to_lowercase();
+	if lower.clone().count() == 1 {
+		lower.next().unwrap()
+	} else {
+		c
+	}
+}
+
+fn html_unescape(s: &str) -> String {
+	s.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&amp;", "&")
+}
+
+/// The character a keyboard-layout-editor legend types without shift: the bottom legend when it
+/// has one, which is where the unshifted symbol goes, and the top one otherwise.
+fn kle_char(legend: &str) -> Option<char> {
+	let legend = html_unescape(legend);
+	let lines = legend.split('\n').collect::<Vec<_>>();
+
+	let unshifted = match lines.get(1) {
+		Some(bottom) if !bottom.is_empty() => bottom,
+		_ => lines[0]
+	};
+	let mut chars = unshifted.chars();
+	match (chars.next(), chars.next()) {
+		(Some(c), None) => Some(lowercase(c)),
+		_ => None
+	}
+}
+
+/// Imports keyboard-layout-editor JSON. Keys whose legend is a single character type characters;
+/// the last three rows with at least ten of those are the rows of the matrix, and the first ten of
+/// them in each row are the keys. Every other key with a character is unmapped.
+pub fn from_kle(json: &str) -> Result<Imported> {
+	let value: Value = serde_json::from_str(json)?;
+	let Some(rows) = value.as_array() else {
+		anyhow::bail!("keyboard-layout-editor JSON should be an array of rows")
+	};
+
+	let rows = rows.iter()
+		.filter_map(|row| row.as_array())
+		.map(|row| row.iter().filter_map(|key| key.as_str()).collect::<Vec<_>>())
+		.collect::<Vec<_>>();
+	let char_keys = |row: &[&str]| row.iter().filter_map(|l| kle_char(l)).collect::<Vec<_>>();
+
+	let matrix_rows = rows.iter()
+		.enumerate()
+		.filter(|(_, row)| char_keys(row).len() >= 10)
+		.map(|(i, _)| i)
+		.collect::<Vec<_>>();
+	if matrix_rows.len() < 3 {
+		anyhow::bail!("only {} rows have ten keys with a character on them, 3 are needed", matrix_rows.len())
+	}
+	let matrix_rows = &matrix_rows[matrix_rows.len() - 3..];
+
+	let mut matrix = String::new();
+	let mut unmapped = Vec::new();
+
+	for (i, row) in rows.iter().enumerate() {
+		let chars = char_keys(row);
+		let mapped = if matrix_rows.contains(&i) { 10 } else { 0 };
+
+		matrix.extend(&chars[..mapped]);
+		unmapped.extend(chars[mapped..].iter().map(|c| c.to_string()));
+	}
+
+	Ok(Imported { layout: FastLayout::try_from(matrix.as_str())?, unmapped })
+}
+
+/// The character of an xkb keysym: a single character, one of the names of ASCII symbols, or a
+/// `U` or `0x100` prefixed code point.
+fn keysym_char(keysym: &str) -> Option<char> {
+	let mut chars = keysym.chars();
+	if let (Some(c), None) = (chars.next(), chars.next()) {
+		return Some(c)
+	}
+	if let Some((c, _)) = KEYSYM_NAMES.iter().find(|(_, name)| *name == keysym) {
+		return Some(*c)
+	}
+	let hex = keysym.strip_prefix('U').or_else(|| keysym.strip_prefix("0x100"))?;
+	u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+}
+
+/// Places the keys a format defines on a qwerty matrix. Keys off the matrix only count as unmapped
+/// when they type something else than on qwerty, as everything else is inherited from qwerty. Fails
+/// when a character ends up on two keys, like one the format moves onto a key that isn't defined.
+fn place(keys: Vec<(Option<&PhysicalKey>, String, Option<char>)>) -> Result<Imported> {
+	let qwerty = QWERTY.chars().collect::<Vec<_>>();
+	let mut matrix = qwerty.clone();
+	let mut defined = vec![false; matrix.len()];
+	let mut unmapped = Vec::new();
+
+	for (physical, name, c) in keys {
+		let pos = physical.and_then(|k| QWERTY.chars().position(|q| q == k.qwerty[0]));
+
+		match (pos, c) {
+			(Some(pos), Some(c)) => {
+				matrix[pos] = c;
+				defined[pos] = true;
+			},
+			(None, Some(c)) if physical.is_some_and(|k| k.qwerty[0] == c) => {},
+			_ => unmapped.push(name)
+		}
+	}
+
+	for (i1, i2) in (0..matrix.len()).tuple_combinations() {
+		if matrix[i1] == matrix[i2] {
+			// qwerty has every character once, so the file defines at least one of both keys
+			let (moved, kept) = if defined[i1] { (i1, i2) } else { (i2, i1) };
+			let undefined = if defined[kept] { "" } else { ", which the file doesn't define" };
+			anyhow::bail!("{} is on both the {} key and the {} key{undefined}", matrix[i1], qwerty[moved], qwerty[kept])
+		}
+	}
+	Ok(Imported { layout: FastLayout::try_from(matrix.into_iter().collect::<String>().as_str())?, unmapped })
+}
+
+/// Imports the first `xkb_symbols` block of an xkb symbols file. Only the first level of every key
+/// is read, and keys the block doesn't define keep what they type on qwerty, as they would when
+/// including `us(basic)`.
+pub fn from_xkb(symbols: &str) -> Result<Imported> {
+	let Some(start) = symbols.find("xkb_symbols") else {
+		anyhow::bail!("there is no xkb_symbols block")
+	};
+	let block = &symbols[start..];
+	let end = block.find("\n};").unwrap_or(block.len());
+	let block = &block[..end];
+
+	let mut keys = Vec::new();
+	for statement in block.split("key <").skip(1) {
+		let Some((name, rest)) = statement.split_once('>') else {
+			continue
+		};
+		// the first list of symbols, skipping brackets like those of `type[Group1]`
+		let levels = rest.match_indices('[')
+			.find(|(i, _)| matches!(rest[..*i].trim_end().chars().last(), Some('{' | '=' | ',')))
+			.and_then(|(i, _)| rest[i + 1..].split_once(']'))
+			.map(|(levels, _)| levels);
+		let keysym = levels.and_then(|l| l.split(',').next()).unwrap_or("").trim();
+
+		keys.push((
+			PHYSICAL_KEYS.iter().find(|k| k.xkb == name),
+			format!("<{name}> {keysym}"),
+			keysym_char(keysym)
+		));
+	}
+	place(keys)
+}
+
+fn klc_char(field: &str) -> Option<char> {
+	let field = field.trim_end_matches('@');
+	let mut chars = field.chars();
+
+	match (chars.next(), chars.next()) {
+		(Some(c), None) => Some(c),
+		_ if field.len() == 4 => u32::from_str_radix(field, 16).ok().and_then(char::from_u32),
+		_ => None
+	}
+}
+
+/// Imports the `LAYOUT` section of a `.klc` file, reading the first shift state column, which is
+/// the unshifted one in files written by the Microsoft Keyboard Layout Creator.
+pub fn from_klc(klc: &str) -> Result<Imported> {
+	const SECTIONS: [&str; 9] = [
+		"DEADKEY", "KEYNAME", "KEYNAME_EXT", "KEYNAME_DEAD", "DESCRIPTIONS", "LANGUAGENAMES",
+		"LIGATURE", "ENDKBD", "SHIFTSTATE"
+	];
+
+	let Some(start) = klc.lines().position(|l| l.split_whitespace().next() == Some("LAYOUT")) else {
+		anyhow::bail!("there is no LAYOUT section")
+	};
+
+	let mut keys = Vec::new();
+	for line in klc.lines().skip(start + 1) {
+		let line = line.split("//").next().unwrap_or("");
+		let fields = line.split_whitespace().collect::<Vec<_>>();
+
+		match fields.first() {
+			None => continue,
+			Some(first) if SECTIONS.contains(first) => break,
+			_ if fields.len() < 4 => anyhow::bail!("'{}' isn't a key of a LAYOUT section", line.trim()),
+			_ => {}
+		}
+
+		let physical = u8::from_str_radix(fields[0], 16).ok()
+			.and_then(|sc| PHYSICAL_KEYS.iter().find(|k| k.scancode == sc));
+		keys.push((physical, format!("scancode {} {}", fields[0], fields[3]), klc_char(fields[3])));
+	}
+	place(keys)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::export::{xkb_symbols, klc_layout};
+	use crate::keyboard::Keyboard;
+
+	const DVORAK: &str = "',.pyfgcrlaoeuidhtns;qjkxbmwvz";
+
+	fn matrix(layout: &FastLayout) -> String {
+		layout.matrix.iter().collect()
+	}
+
+	#[test]
+	fn kle() {
+		let json = r##"[
+			{"name": "ansi"},
+			["~\n`", "!\n1", "@\n2", "#\n3", "$\n4", "%\n5", "^\n6", "&\n7", "*\n8", "(\n9", ")\n0", "{\n[", "}\n]", {"w": 2}, "Backspace"],
+			[{"w": 1.5}, "Tab", "\"\n'", "&lt;\n,", "&gt;\n.", "P", "Y", "F", "G", "C", "R", "L", "?\n/", "+\n=", {"w": 1.5}, "|\n\\"],
+			[{"w": 1.75}, "Caps Lock", "A", "O", "E", "U", "I", "D", "H", "T", "N", "S", "_\n-", {"w": 2.25}, "Enter"],
+			[{"w": 2.25}, "Shift", ":\n;", "Q", "J", "K", "X", "B", "M", "W", "V", "Z", {"w": 2.75}, "Shift"],
+			[{"w": 6.25}, ""]
+		]"##;
+		let imported = from_kle(json).unwrap();
+
+		assert_eq!(matrix(&imported.layout), DVORAK);
+		assert_eq!(imported.unmapped.len(), 13 + 3 + 1);
+		assert!(imported.unmapped.contains(&"-".to_string()));
+
+		assert!(from_kle(r#"[["Q", "W"]]"#).is_err());
+	}
+
+	#[test]
+	fn xkb() {
+		let dvorak = FastLayout::try_from(DVORAK).unwrap();
+		let symbols = xkb_symbols(&dvorak, &Keyboard::default(), "Dvorak").unwrap();
+		let imported = from_xkb(&symbols).unwrap();
+
+		assert_eq!(matrix(&imported.layout), DVORAK);
+		assert!(imported.unmapped.is_empty());
+
+		let symbols = "xkb_symbols \"basic\" {\n    key <AD01> { type[Group1] = \"ALPHABETIC\", [ odiaeresis, \
+			Odiaeresis ] };\n    key <AD02> { [ U00E9, U00C9 ] };\n    key <AE01> { [ exclam, 1 ] };\n};";
+		let imported = from_xkb(symbols).unwrap();
+
+		assert_eq!(imported.layout.matrix[0], 'q');
+		assert_eq!(imported.layout.matrix[1], 'é');
+		assert_eq!(imported.unmapped, vec!["<AD01> odiaeresis", "<AE01> exclam"]);
+
+		// a stays on the qwerty a key the block doesn't define
+		let symbols = "xkb_symbols \"basic\" {\n    key <AD01> { [ a, A ] };\n};";
+		let error = from_xkb(symbols).err().unwrap().to_string();
+		assert_eq!(error, "a is on both the q key and the a key, which the file doesn't define");
+	}
+
+	#[test]
+	fn klc() {
+		let dvorak = FastLayout::try_from(DVORAK).unwrap();
+		let klc = klc_layout(&dvorak, &Keyboard::default(), "Dvorak").unwrap();
+		let imported = from_klc(&klc).unwrap();
+
+		assert_eq!(matrix(&imported.layout), DVORAK);
+		assert!(imported.unmapped.is_empty());
+
+		let klc = "LAYOUT\n\n10\tQ\t1\t00e9\t00c9\n02\t1\t0\t0021\t1\t// swapped\n53\tDECIMAL\t0\t002e\t002e\n\nENDKBD";
+		let imported = from_klc(klc).unwrap();
+
+		assert_eq!(imported.layout.matrix[0], 'é');
+		assert_eq!(imported.unmapped, vec!["scancode 02 0021", "scancode 53 002e"]);
+	}
+}
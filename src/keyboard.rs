@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use anyhow::Result;
+use fxhash::FxHashMap;
+use serde::Deserialize;
+
+use crate::utility::*;
+
+/// Where keyboard files are looked up by name.
+const KEYBOARD_DIR: &str = "static/keyboards";
+
+/// A physical key: where its center is in key widths, x to the right and y down, which finger
+/// presses it and how much effort that takes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+	pub x: f64,
+	pub y: f64,
+	pub finger: usize,
+	pub effort: f64,
+	/// Whether its finger rests on it.
+	pub home: bool
+}
+
+impl Key {
+	pub const fn is_left_hand(&self) -> bool {
+		is_left_hand(self.finger)
+	}
+}
+
+#[derive(Deserialize)]
+struct KeyLoad {
+	row: Option<i32>,
+	col: Option<i32>,
+	x: f64,
+	y: f64,
+	finger: usize,
+	effort: f64,
+	#[serde(default)]
+	home: bool
+}
+
+#[derive(Deserialize)]
+struct KeyboardLoad {
+	name: String,
+	#[serde(default)]
+	iso: bool,
+	keys: Vec<KeyLoad>
+}
+
+/// The geometry of a physical keyboard, read from a file in `static/keyboards` that lists all of
+/// its keys. Hands follow from the fingers, which are numbered like a fingering.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyboard {
+	pub name: String,
+	/// Whether there's an ISO key left of the bottom row.
+	pub iso: bool,
+	keys: Vec<Key>,
+	/// The key at every row and column of the layout grid.
+	grid: FxHashMap<(i32, i32), usize>
+}
+
+impl Default for Keyboard {
+	fn default() -> Self {
+		Self::from_toml(include_str!("../static/keyboards/ansi_angle.toml"))
+			.expect("the default keyboard file is broken")
+	}
+}
+
+impl Keyboard {
+	/// Loads `static/keyboards/{name}.toml`, with the spaces of the name as underscores. Plain row
+	/// staggered boards can also be called ansi, iso or jis.
+	pub fn load(name: &str) -> Result<Self> {
+		let name = name.trim().to_lowercase().replace(' ', "_");
+		let name = match name.as_str() {
+			"ansi" | "iso" | "jis" => "rowstag",
+			name => name
+		};
+		Self::from_file(Path::new(KEYBOARD_DIR).join(format!("{name}.toml")))
+	}
+
+	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let content = std::fs::read_to_string(&path)
+			.map_err(|e| anyhow::anyhow!("couldn't read {}: {e}", path.as_ref().display()))?;
+		Self::from_toml(&content)
+	}
+
+	/// Parses a keyboard file. Keys with a row and column are where a layout grid puts its keys,
+	/// thumb keys are used for the thumb keys of a shape and other keys aren't used by layouts.
+	pub fn from_toml(content: &str) -> Result<Self> {
+		let load: KeyboardLoad = toml::from_str(content)?;
+
+		let mut keys = Vec::with_capacity(load.keys.len());
+		let mut grid = FxHashMap::default();
+
+		for k in load.keys {
+			if k.finger >= FINGER_COUNT {
+				anyhow::bail!("finger {} doesn't exist, fingers go from 0 to {}", k.finger, FINGER_COUNT - 1)
+			}
+			match (k.row, k.col) {
+				(Some(row), Some(col)) => if grid.insert((row, col), keys.len()).is_some() {
+					anyhow::bail!("there are two keys at row {row}, column {col}")
+				},
+				(None, None) => {},
+				_ => anyhow::bail!("the key at {}, {} needs both a row and a column, or neither", k.x, k.y)
+			}
+			keys.push(Key { x: k.x, y: k.y, finger: k.finger, effort: k.effort, home: k.home });
+		}
+		if grid.is_empty() {
+			anyhow::bail!("{} doesn't have any keys with a row and a column", load.name)
+		}
+
+		Ok(Self { name: load.name, iso: load.iso, keys, grid })
+	}
+
+	/// The thumb keys of one thumb a shape with `count` of them uses: the ones closest to its home
+	/// key, or to the middle of the board without one, from left to right.
+	fn thumb_keys(&self, thumb: usize, count: usize) -> Result<Vec<usize>, String> {
+		let mut thumbs = (0..self.keys.len())
+			.filter(|&i| self.keys[i].finger == thumb)
+			.collect::<Vec<_>>();
+		if thumbs.len() < count {
+			return Err(format!("{} has {} thumb keys per hand, not {count}", self.name, thumbs.len()))
+		}
+
+		let inner = |&i: &usize| if thumb == LEFT_THUMB { self.keys[i].x } else { -self.keys[i].x };
+		let home = thumbs.iter().copied()
+			.find(|&i| self.keys[i].home)
+			.or_else(|| thumbs.iter().copied().max_by(|i1, i2| inner(i1).total_cmp(&inner(i2))));
+
+		if let Some(home) = home {
+			let home = self.keys[home];
+			let distance = |&i: &usize| (self.keys[i].x - home.x).powi(2) + (self.keys[i].y - home.y).powi(2);
+			thumbs.sort_by(|i1, i2| distance(i1).total_cmp(&distance(i2)));
+		}
+		thumbs.truncate(count);
+		thumbs.sort_by(|&i1, &i2| self.keys[i1].x.total_cmp(&self.keys[i2].x));
+		Ok(thumbs)
+	}
+
+	/// The first row and column of the layout grid a shape covers. Row 0 is the number row and
+	/// columns 0 to 9 are the 3x10 alpha block, so outer pinky columns are -1 and 10.
+	const fn grid_start(shape: LayoutShape) -> (i32, i32) {
+		(4 - shape.rows as i32, -(shape.col_offset() as i32))
+	}
+
+	/// Where every key of a shape is in the keys of the file, or the first one that's missing.
+	fn indices(&self, shape: LayoutShape) -> Result<Vec<usize>, String> {
+		let (row0, col0) = Self::grid_start(shape);
+		let mut res = (0..shape.grid_len())
+			.map(|i| {
+				let (row, col) = (row0 + (i / shape.cols) as i32, col0 + (i % shape.cols) as i32);
+				self.grid.get(&(row, col)).copied()
+					.ok_or_else(|| format!("{} has no key at row {row}, column {col}", self.name))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		res.extend(self.thumb_keys(LEFT_THUMB, shape.thumbs / 2)?);
+		res.extend(self.thumb_keys(RIGHT_THUMB, shape.thumbs / 2)?);
+		Ok(res)
+	}
+
+	/// Whether the keyboard has all the keys of a shape.
+	pub fn fits(&self, shape: LayoutShape) -> Result<()> {
+		self.indices(shape).map(|_| ()).map_err(anyhow::Error::msg)
+	}
+
+	/// The keys of a shape, with positions relative to where its top left grid position is. Panics
+	/// when the keyboard doesn't have the shape, which `fits` checks.
+	pub fn keys(&self, shape: LayoutShape) -> Vec<Key> {
+		let (row0, col0) = Self::grid_start(shape);
+		let indices = self.indices(shape).unwrap_or_else(|e| panic!("{e}"));
+
+		indices.into_iter()
+			.map(|i| {
+				let key = self.keys[i];
+				Key { x: key.x - col0 as f64, y: key.y - row0 as f64, ..key }
+			})
+			.collect()
+	}
+
+	/// The finger every key of a shape is pressed with.
+	pub fn fingering(&self, shape: LayoutShape) -> Fingering {
+		self.keys(shape).iter().map(|k| k.finger).collect()
+	}
+
+	/// Center of every key of a shape, relative to its top left grid position.
+	pub fn key_positions(&self, shape: LayoutShape) -> Vec<(f64, f64)> {
+		self.keys(shape).iter().map(|k| (k.x, k.y)).collect()
+	}
+
+	/// The key of a shape every finger rests on, when pressing keys with `fingering`: its home key
+	/// in the file, or the key of the finger closest to that. `None` for fingers that don't press
+	/// any keys.
+	pub fn home_keys(&self, shape: LayoutShape, fingering: &[usize]) -> [Option<usize>; FINGER_COUNT] {
+		let keys = self.keys(shape);
+		let homes: [Option<(f64, f64)>; FINGER_COUNT] = std::array::from_fn(|finger| {
+			self.keys.iter()
+				.find(|k| k.home && k.finger == finger)
+				.map(|k| (k.x, k.y))
+		});
+		let (row0, col0) = Self::grid_start(shape);
+
+		std::array::from_fn(|finger| {
+			let on_finger = (0..keys.len()).filter(|&i| fingering[i] == finger);
+			match homes[finger] {
+				Some((hx, hy)) => {
+					let (hx, hy) = (hx - col0 as f64, hy - row0 as f64);
+					on_finger.min_by(|&i1, &i2| {
+						let d1 = (keys[i1].x - hx).powi(2) + (keys[i1].y - hy).powi(2);
+						let d2 = (keys[i2].x - hx).powi(2) + (keys[i2].y - hy).powi(2);
+						d1.total_cmp(&d2)
+					})
+				},
+				None => on_finger.min_by(|&i1, &i2| keys[i1].effort.total_cmp(&keys[i2].effort))
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keyboards() {
+		let shape = LayoutShape::default();
+		let ansi = Keyboard::default();
+		assert_eq!(ansi, Keyboard::load("ansi angle").unwrap());
+		assert_eq!(ansi.key_positions(shape)[10], (0.25, 1.0));
+		assert_eq!(ansi.key_positions(shape)[20], (0.75, 2.0));
+		assert_eq!(Keyboard::load("iso").unwrap().name, "rowstag");
+		assert!(Keyboard::load("iso angle").unwrap().iso);
+
+		let big = LayoutShape::try_from("4x11+2").unwrap();
+		let keys = Keyboard::load("colstag").unwrap().keys(big);
+		assert_eq!(keys.len(), 46);
+		assert_eq!((keys[0].x, keys[0].y, keys[0].effort), (0.0, 0.5, 4.4));
+		assert_eq!((keys[21].finger, keys[21].effort), (7, 3.6));
+		assert_eq!(keys[44].finger, LEFT_THUMB);
+		assert!(!keys[45].is_left_hand());
+
+		assert!(Keyboard::load("no such keyboard").is_err());
+		assert!(Keyboard::from_toml("name = \"small\"\nkeys = [{ x = 0.0, y = 0.0, finger = 0, effort = 1.0 }]").is_err());
+	}
+
+	#[test]
+	fn keyboard_shapes() {
+		let corne = Keyboard::load("corne").unwrap();
+		assert!(corne.fits(LayoutShape::try_from("3x12+4").unwrap()).is_ok());
+		assert!(corne.fits(LayoutShape::try_from("4x10").unwrap()).is_err());
+		// the 4 of its 6 thumb keys closest to the home keys of the thumbs
+		let keys = corne.keys(LayoutShape::try_from("3x10+4").unwrap());
+		assert_eq!(keys[30..].iter().map(|k| k.finger).collect::<Vec<_>>(), [8, 8, 9, 9]);
+		assert!(keys[30].x > 2.0 && keys[33].x < 8.0);
+
+		let ergodox = Keyboard::load("ergodox").unwrap();
+		let shape = LayoutShape::try_from("4x12+4").unwrap();
+		assert!(ergodox.fits(shape).is_ok());
+		assert!(ergodox.keys.len() > shape.len());
+
+		let shape = LayoutShape::default();
+		let fingering = Keyboard::default().fingering(shape);
+		let homes = Keyboard::default().home_keys(shape, &fingering);
+		assert_eq!(homes[..8], [Some(10), Some(11), Some(12), Some(13), Some(16), Some(17), Some(18), Some(19)]);
+		assert_eq!(homes[LEFT_THUMB], None);
+		let thumbs = LayoutShape::try_from("3x10+4").unwrap();
+		let homes = corne.home_keys(thumbs, &corne.fingering(thumbs));
+		assert_eq!((homes[LEFT_THUMB], homes[RIGHT_THUMB]), (Some(31), Some(32)));
+	}
+}
@@ -12,6 +12,9 @@ pub type CharacterData = smallmap::Map<char, f64>;
 pub type BigramData = FxHashMap<[char; 2], f64>;
 pub type TrigramData = Vec<([char; 3], f64)>;
 
+/// The skip distances there is skipgram data for.
+pub const SKIP_DISTANCES: usize = 3;
+
 #[derive(Deserialize)]
 struct LanguageDataInter {
 	pub language: String,
@@ -23,7 +26,82 @@ struct LanguageDataInter {
 	pub trigrams: IndexMap<String, f64>,
 }
 
+/// A key that types the previous character again. With `magic`, it types the listed character
+/// instead after the ones in there, like an adaptive key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepeatKey {
+	pub key: char,
+	pub magic: FxHashMap<char, char>
+}
+
+impl RepeatKey {
+	pub fn new(key: char) -> Self {
+		Self { key, magic: FxHashMap::default() }
+	}
+
+	pub fn with_magic(mut self, magic: FxHashMap<char, char>) -> Self {
+		self.magic = magic;
+		self
+	}
+
+	/// What the key types after `prev`.
+	pub fn output(&self, prev: char) -> char {
+		self.magic.get(&prev).copied().unwrap_or(prev)
+	}
+
+	/// Replaces every character the key would type after the one before it with the key.
+	pub fn rewrite(&self, ngram: &str) -> String {
+		let mut prev = None;
+		ngram.chars()
+			.map(|c| {
+				let typed = match prev {
+					Some(p) if c != ' ' && self.output(p) == c => self.key,
+					_ => c
+				};
+				prev = Some(c);
+				typed
+			})
+			.collect()
+	}
+}
+
 impl LanguageDataInter {
+	/// Types everything the repeat key can type with it. Skipgrams stay as they are, as the
+	/// character they skip over isn't known. Returns the bigrams that were rewritten as well.
+	fn with_repeat(mut self, repeat: &RepeatKey) -> (Self, BigramData) {
+		let mut repeated = BigramData::default();
+
+		for (bigram, &f) in &self.bigrams {
+			let rewritten = repeat.rewrite(bigram);
+			if &rewritten != bigram {
+				let bv = bigram.chars().collect::<Vec<_>>();
+				repeated.insert([bv[0], bv[1]], f);
+
+				if let Some(freq) = self.characters.get_mut(&bv[1]) {
+					*freq = (*freq - f).max(0.0);
+				}
+				*self.characters.entry(repeat.key).or_insert(0.0) += f;
+			}
+		}
+
+		let rewrite_all = |data: FxHashMap<String, f64>| {
+			let mut res = FxHashMap::default();
+			for (ngram, f) in data {
+				*res.entry(repeat.rewrite(&ngram)).or_insert(0.0) += f;
+			}
+			res
+		};
+		self.bigrams = rewrite_all(self.bigrams);
+
+		let mut trigrams = IndexMap::new();
+		for (trigram, f) in self.trigrams {
+			*trigrams.entry(repeat.rewrite(&trigram)).or_insert(0.0) += f;
+		}
+		self.trigrams = trigrams;
+
+		(self, repeated)
+	}
+
 	fn get_char_data(&self, data: &FxHashMap<char, f64>) -> CharacterData {
 		let mut res = CharacterData::new();
 		for (c, f) in data.into_iter() {
@@ -65,6 +143,8 @@ pub struct LanguageData {
 	pub skipgrams3: BigramData,
 	pub weighted_bigrams: BigramData,
 	pub trigrams: TrigramData,
+	/// Bigrams a repeat key types the second character of, as they were before.
+	pub repeated: BigramData,
 	pub language: String
 }
 
@@ -83,18 +163,36 @@ impl From<LanguageDataInter> for LanguageData {
 
 		Self {
 			characters, bigrams, skipgrams, skipgrams2, skipgrams3,
-			weighted_bigrams, trigrams, language: inter.language,
+			weighted_bigrams, trigrams, repeated: BigramData::default(), language: inter.language,
 		}
 	}
 }
 
 impl LanguageData {
 	pub fn new(text: &str) -> Result<LanguageData> {
+		Self::new_with_repeat(text, None)
+	}
+
+	/// Loads data where everything `repeat` can type is typed with it, before ngrams with a
+	/// doubled character are left out.
+	pub fn new_with_repeat(text: &str, repeat: Option<&RepeatKey>) -> Result<LanguageData> {
 		let data: LanguageDataInter = serde_json::from_str(text)?;
-		Ok(LanguageData::from(data))
+
+		match repeat {
+			Some(repeat) => {
+				let (data, repeated) = data.with_repeat(repeat);
+				Ok(LanguageData { repeated, ..LanguageData::from(data) })
+			}
+			None => Ok(LanguageData::from(data))
+		}
 	}
 
 	pub fn from_file<P>(base_path: P, language: &str) -> Result<LanguageData>
+		where P: AsRef<Path> {
+		Self::from_file_with_repeat(base_path, language, None)
+	}
+
+	pub fn from_file_with_repeat<P>(base_path: P, language: &str, repeat: Option<&RepeatKey>) -> Result<LanguageData>
 		where P: AsRef<Path> {
 		let file_path = base_path.as_ref().join(language.to_lowercase() + ".json");
 		let mut file = File::open(file_path)?;
@@ -102,9 +200,159 @@ impl LanguageData {
 		let mut contents = String::new();
 		file.read_to_string(&mut contents)?;
 
-		let data: LanguageDataInter = serde_json::from_str(contents.as_str())?;
-		let res = LanguageData::from(data);
+		Self::new_with_repeat(&contents, repeat)
+	}
+	/// The skipgrams of every skip distance, from skipping one character on.
+	pub fn skipgrams(&self) -> [&BigramData; SKIP_DISTANCES] {
+		[&self.skipgrams, &self.skipgrams2, &self.skipgrams3]
+	}
+
+	/// Rewrites every character in `layered` as the keys pressed to type it, its layer key followed
+	/// by the key it sits on, so layer switches count in every metric. This needs data that keeps
+	/// shifted characters and symbols, like data loaded with `Translator::raw(false)`. Skipgrams
+	/// are approximate, skipping over a layered character still counts as skipping one key.
+	pub fn with_layers(&self, layered: &FxHashMap<char, [char; 2]>) -> LanguageData {
+		let keys = |c: char| match layered.get(&c) {
+			Some(keys) => keys.to_vec(),
+			None => vec![c]
+		};
 
-		Ok(res)
+		let mut characters = FxHashMap::default();
+		for &(c, f) in self.characters.iter() {
+			for k in keys(c) {
+				*characters.entry(k).or_insert(0.0) += f;
+			}
+		}
+
+		// every key pressed starts a new ngram, so each one starts in the keys of the first char
+		let mut bigrams = BigramData::default();
+		let mut skipgrams = Self::layered_ends(&self.skipgrams, &keys);
+		for (&[c1, c2], &f) in &self.bigrams {
+			let (first, seq) = (keys(c1), [keys(c1), keys(c2)].concat());
+			for i in 0..first.len() {
+				*bigrams.entry([seq[i], seq[i + 1]]).or_insert(0.0) += f;
+			}
+			if first.len() == 2 {
+				*skipgrams.entry([seq[0], seq[2]]).or_insert(0.0) += f;
+			}
+		}
+
+		let mut trigrams = FxHashMap::<[char; 3], f64>::default();
+		for &([c1, c2, c3], f) in &self.trigrams {
+			let (first, seq) = (keys(c1), [keys(c1), keys(c2), keys(c3)].concat());
+			for i in 0..first.len() {
+				*trigrams.entry([seq[i], seq[i + 1], seq[i + 2]]).or_insert(0.0) += f;
+			}
+		}
+		let mut trigrams = trigrams.into_iter()
+			.filter(|(t, _)| t[0] != t[1] && t[1] != t[2])
+			.collect::<TrigramData>();
+		trigrams.sort_by(|(_, f1), (_, f2)| f2.partial_cmp(f1).unwrap());
+
+		let characters = Self::normalized(characters, self.characters.iter().map(|(_, f)| f).sum());
+		let mut res = CharacterData::new();
+		for (c, f) in characters {
+			res.insert(c, f);
+		}
+
+		Self {
+			characters: res,
+			bigrams: Self::normalized(bigrams, self.bigrams.values().sum()),
+			skipgrams: Self::normalized(skipgrams, self.skipgrams.values().sum()),
+			skipgrams2: Self::normalized(
+				Self::layered_ends(&self.skipgrams2, &keys), self.skipgrams2.values().sum()
+			),
+			skipgrams3: Self::normalized(
+				Self::layered_ends(&self.skipgrams3, &keys), self.skipgrams3.values().sum()
+			),
+			weighted_bigrams: BigramData::default(),
+			trigrams: Self::normalized(trigrams, self.trigrams.iter().map(|(_, f)| f).sum()),
+			repeated: self.repeated.clone(),
+			language: self.language.clone()
+		}
+	}
+
+	fn layered_ends(data: &BigramData, keys: &impl Fn(char) -> Vec<char>) -> BigramData {
+		let mut res = BigramData::default();
+		for (&[c1, c2], &f) in data {
+			let end = *keys(c1).last().unwrap();
+			*res.entry([end, keys(c2)[0]]).or_insert(0.0) += f;
+		}
+		res
 	}
-}
\ No newline at end of file
+
+	/// Scales frequencies back to the total they had before layer keys were added.
+	fn normalized<K, T>(data: T, total: f64) -> T
+		where T: IntoIterator<Item = (K, f64)> + FromIterator<(K, f64)> + Clone {
+		let new_total: f64 = data.clone().into_iter().map(|(_, f)| f).sum();
+		if new_total == 0.0 {
+			return data
+		}
+		data.into_iter().map(|(k, f)| (k, f * total / new_total)).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utility::ApproxEq;
+
+	#[test]
+	fn with_layers() {
+		let data = LanguageData::new(r#"{
+			"language": "layers",
+			"characters": { "a": 0.5, "B": 0.25, "c": 0.25 },
+			"bigrams": { "aB": 0.5, "Bc": 0.5 },
+			"skipgrams": { "ac": 1.0 },
+			"skipgrams2": {},
+			"skipgrams3": {},
+			"trigrams": { "aBc": 1.0 }
+		}"#).unwrap();
+
+		let layered = FxHashMap::from_iter([('B', ['^', 'b'])]);
+		let data = data.with_layers(&layered);
+
+		// typed as a ^ b c
+		assert!(data.characters.get(&'^').unwrap().approx_eq_dbg(0.2, 9));
+		assert_eq!(data.characters.get(&'B'), None);
+		for bigram in [['a', '^'], ['^', 'b'], ['b', 'c']] {
+			assert!(data.bigrams[&bigram].approx_eq_dbg(1.0/3.0, 9));
+		}
+		assert!(data.skipgrams[&['a', 'c']].approx_eq_dbg(2.0/3.0, 9));
+		assert!(data.skipgrams[&['^', 'c']].approx_eq_dbg(1.0/3.0, 9));
+		assert_eq!(data.trigrams, vec![(['a', '^', 'b'], 1.0)]);
+	}
+
+	#[test]
+	fn repeat_key() {
+		let repeat = RepeatKey::new('@');
+		assert_eq!(repeat.rewrite("tree"), "tre@");
+		assert_eq!(repeat.rewrite("eee"), "e@@");
+		assert_eq!(repeat.rewrite("a  b"), "a  b");
+
+		let magic = RepeatKey::new('@').with_magic(FxHashMap::from_iter([('e', 'u')]));
+		assert_eq!(magic.rewrite("eue"), "e@e");
+		assert_eq!(magic.rewrite("ee"), "ee");
+
+		let text = r#"{
+			"language": "repeat",
+			"characters": { "e": 0.5, "l": 0.25, "t": 0.25 },
+			"bigrams": { "ee": 0.25, "el": 0.25, "ll": 0.25, "lt": 0.25 },
+			"skipgrams": { "et": 1.0 },
+			"skipgrams2": {},
+			"skipgrams3": {},
+			"trigrams": { "eel": 0.5, "llt": 0.25, "elt": 0.25 }
+		}"#;
+		let data = LanguageData::new_with_repeat(text, Some(&repeat)).unwrap();
+
+		assert!(data.characters.get(&'@').unwrap().approx_eq_dbg(0.5, 9));
+		assert!(data.characters.get(&'e').unwrap().approx_eq_dbg(0.25, 9));
+		assert_eq!(data.bigrams.get(&['e', 'e']), None);
+		assert!(data.bigrams[&['e', '@']].approx_eq_dbg(0.25, 9));
+		assert_eq!(data.trigrams.len(), 3);
+		assert_eq!(data.repeated.len(), 2);
+
+		// without the repeat key the doubled trigrams are left out
+		assert_eq!(LanguageData::new(text).unwrap().trigrams.len(), 1);
+	}
+}
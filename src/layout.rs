@@ -1,13 +1,19 @@
+use fxhash::FxHashMap;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+
 use crate::utility::*;
+use crate::keyboard::Keyboard;
 use crate::generate::{Matrix, CharToFinger};
 use crate::trigram_patterns::{TrigramPattern, TRIGRAM_COMBINATIONS};
 
 pub trait Layout<T: Copy + Default> {
-	fn new() -> Self;
+	fn new(shape: LayoutShape, fingering: Fingering) -> Self;
 
-	fn random(available_chars: [T; 30]) -> Self;
+	fn random(available_chars: Vec<T>, shape: LayoutShape, fingering: Fingering) -> Self;
 
-	fn random_pins(layout_chars: [T; 30], pins: &[usize]) -> Self;
+	fn random_pins(layout_chars: Vec<T>, pins: &[usize], shape: LayoutShape, fingering: Fingering) -> Self;
 
 	fn c(&self, i: usize) -> T;
 
@@ -27,92 +33,517 @@ pub trait Layout<T: Copy + Default> {
 
 	fn swap_indexes(&mut self);
 
-	fn get_index(&self, index: usize) -> [T; 6];
+	fn get_index(&self, index: usize) -> Vec<T>;
 
 	fn get_trigram_pattern(&self, trigram: &[T; 3]) -> TrigramPattern;
 
 	unsafe fn get_trigram_pattern_unchecked(&self, trigram: &[char; 3]) -> TrigramPattern;
 }
 
+/// A layer on top of the base layer, reached by holding `key`. Its characters move along with the
+/// base layer key they sit on. Positions without a character are spaces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+	pub name: String,
+	pub key: char,
+	pub matrix: Matrix<char>
+}
+
+/// Reads the layers of a `.kb` file. Each one is a block that starts with `layer <name> <key>`
+/// followed by rows in the same shape as the base layer, where `_` leaves a position empty.
+pub fn layers_from_kb(kb: &str, shape: LayoutShape) -> anyhow::Result<Vec<Layer>> {
+	let mut layers = Vec::new();
+	let mut lines = kb.lines();
+
+	while let Some(line) = lines.next() {
+		let header = line.split_whitespace().collect::<Vec<_>>();
+		if header.first() != Some(&"layer") {
+			continue
+		}
+		let (name, key) = match header[1..] {
+			[name, key] if key.chars().count() == 1 => (name, key.chars().next().unwrap()),
+			_ => anyhow::bail!("layers are declared as 'layer <name> <key>', not '{line}'")
+		};
+
+		let rows = lines.by_ref().take_while(|l| !l.trim().is_empty()).join("\n");
+		match format_layout_str(rows) {
+			Some((keys, layer_shape)) if layer_shape == shape => layers.push(Layer {
+				name: name.to_string(),
+				key,
+				matrix: keys.chars().map(|c| if c == '_' { ' ' } else { c }).collect()
+			}),
+			_ => anyhow::bail!("layer {name} should have the same {shape} shape as the base layer")
+		}
+	}
+	Ok(layers)
+}
+
+/// A character typed by pressing all of `keys` at the same time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combo {
+	pub keys: Vec<usize>,
+	pub output: char
+}
+
+/// Reads the combos of a `.kb` file, lines like `combo q w e` for typing q by pressing the keys
+/// with w and e on them together, where `_` is the key with space on it.
+pub fn combos_from_kb(kb: &str, matrix: &[char]) -> anyhow::Result<Vec<Combo>> {
+	let mut combos = Vec::new();
+
+	for line in kb.lines() {
+		let words = line.split_whitespace().collect::<Vec<_>>();
+		if words.first() != Some(&"combo") {
+			continue
+		}
+		let chars = words[1..].iter()
+			.map(|w| match w.chars().collect::<Vec<_>>()[..] {
+				['_'] => Some(' '),
+				[c] => Some(c),
+				_ => None
+			})
+			.collect::<Option<Vec<_>>>();
+
+		let Some([output, keys @ ..]) = chars.as_deref() else {
+			anyhow::bail!("combos are declared as 'combo <char> <key> <key>..', not '{line}'")
+		};
+		let keys = keys.iter()
+			.map(|k| matrix.iter().position(|c| c == k))
+			.collect::<Option<Vec<_>>>();
+
+		match keys {
+			Some(keys) if keys.len() >= 2 && keys.iter().all_unique() => {
+				combos.push(Combo { keys, output: *output })
+			}
+			Some(_) => anyhow::bail!("combo {output} should have at least two different keys"),
+			None => anyhow::bail!("combo {output} uses a key that isn't on the layout")
+		}
+	}
+	Ok(combos)
+}
+
+/// Ways of rearranging a layout that can be treated as giving the same layout.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+	/// Left and right hand swapped, every row read backwards.
+	Mirror,
+	/// The two index columns of a single hand swapped.
+	IndexOrder,
+	/// A rearrangement of the user's own, as the position every key is taken from.
+	Custom(Vec<usize>)
+}
+
+impl Symmetry {
+	pub const ALL: [Symmetry; 2] = [Self::Mirror, Self::IndexOrder];
+
+	/// Checks that layouts this symmetry turns into each other score the same: the shape has to be
+	/// the same on both hands for `Mirror`, a `Custom` one has to take every key once, and every
+	/// rearrangement has to move keys onto keys of the same finger, or all of them onto the mirrored
+	/// finger, with the same effort.
+	pub fn check(&self, shape: LayoutShape, fingering: &[usize], effort: &[f64]) -> anyhow::Result<()> {
+		if *self == Self::Mirror && shape.cols != 10 + 2 * shape.col_offset() {
+			anyhow::bail!("{self:?} needs as many columns on either hand, which a {shape} layout doesn't have")
+		}
+		if let Self::Custom(permutation) = self {
+			let mut sorted = permutation.clone();
+			sorted.sort_unstable();
+			if sorted != (0..shape.len()).collect::<Vec<_>>() {
+				anyhow::bail!("{self:?} should have every key of a {shape} layout exactly once")
+			}
+		}
+		let mirrored = |f: usize| if f < LEFT_THUMB { 7 - f } else { LEFT_THUMB + RIGHT_THUMB - f };
+
+		for permutation in self.permutations(shape) {
+			let moves = permutation.iter().enumerate().map(|(i, &j)| (fingering[j], fingering[i]));
+			let same = moves.clone().all(|(f1, f2)| f1 == f2);
+			if !same && !moves.clone().all(|(f1, f2)| mirrored(f1) == f2) {
+				anyhow::bail!("{self:?} moves keys onto other fingers, so it changes the fingering")
+			}
+			if let Some((i, &j)) = permutation.iter().enumerate().find(|&(i, &j)| (effort[i] - effort[j]).abs() > 1e-9) {
+				anyhow::bail!("{self:?} moves key {j} onto key {i}, which has a different effort")
+			}
+		}
+		Ok(())
+	}
+
+	/// The rearrangements this symmetry allows, as the position every key is taken from.
+	fn permutations(&self, shape: LayoutShape) -> Vec<Vec<usize>> {
+		let cols = shape.cols;
+		let index = shape.col_offset() + 3;
+		let swap_cols = |pairs: &[(usize, usize)]| {
+			let mut res = (0..shape.len()).collect::<Vec<_>>();
+			for row in 0..shape.rows {
+				for &(c1, c2) in pairs {
+					res.swap(row * cols + c1, row * cols + c2);
+				}
+			}
+			res
+		};
+
+		match self {
+			Self::Mirror => {
+				let mut res = (0..shape.len()).collect::<Vec<_>>();
+				for row in 0..shape.rows {
+					res[row * cols..(row + 1) * cols].reverse();
+				}
+				res[shape.grid_len()..].reverse();
+				vec![res]
+			}
+			Self::IndexOrder => vec![
+				swap_cols(&[(index, index + 1)]),
+				swap_cols(&[(index + 2, index + 3)])
+			],
+			Self::Custom(permutation) => {
+				assert_eq!(permutation.len(), shape.len(), "{self:?} doesn't fit a {shape} layout");
+				vec![permutation.clone()]
+			}
+		}
+	}
+}
+
+/// Collapses layouts that are the same under `symmetries` into their canonical form, scored with
+/// `score`, with how many times each was found, best scores first.
+pub fn distinct_layouts<I, S>(layouts: I, symmetries: &[Symmetry], score: S) -> Vec<(FastLayout, usize)>
+	where I: IntoIterator<Item = FastLayout>, S: Fn(&FastLayout) -> f64 {
+	let mut res = IndexMap::<String, (FastLayout, usize)>::new();
+
+	for layout in layouts {
+		let canonical = layout.canonical(symmetries);
+		let (_, count) = res.entry(canonical.key_str()).or_insert_with(|| {
+			let mut canonical = canonical;
+			canonical.score = score(&canonical);
+			(canonical, 0)
+		});
+		*count += 1;
+	}
+
+	let mut res = res.into_iter().map(|(_, found)| found).collect::<Vec<_>>();
+	res.sort_by(|(a, _), (b, _)| b.score.partial_cmp(&a.score).unwrap());
+	res
+}
+
 #[derive(Default, Clone)]
 pub struct FastLayout {
 	pub matrix: Matrix<char>,
 	pub char_to_finger: CharToFinger<char>,
+	/// The key every character is on.
+	pub char_to_key: CharToFinger<char>,
+	pub shape: LayoutShape,
+	pub fingering: Fingering,
+	pub layers: Vec<Layer>,
+	pub combos: Vec<Combo>,
 	pub score: f64
 }
 
-impl From<[char; 30]> for FastLayout {
-    fn from(layout: [char; 30]) -> Self {
-        let mut new_layout = FastLayout::new();
+/// A layout of `shape` with every key pressed by the finger `fingering` gives it.
+impl From<(Vec<char>, LayoutShape, Fingering)> for FastLayout {
+    fn from((layout, shape, fingering): (Vec<char>, LayoutShape, Fingering)) -> Self {
+		assert_eq!(layout.len(), shape.len(), "a {shape} layout needs {} keys", shape.len());
+
+        let mut new_layout = FastLayout::new(shape, fingering);
 
 		for (i, c) in layout.into_iter().enumerate() {
 			new_layout.matrix[i] = c;
-			new_layout.char_to_finger.insert(c, COL_TO_FINGER[i%10]);
+			new_layout.char_to_finger.insert(c, new_layout.fingering[i]);
+			new_layout.char_to_key.insert(c, i);
 		}
 		new_layout
     }
 }
 
+lazy_static! {
+	/// The keyboard layouts written out as characters are typed on.
+	static ref DEFAULT_KEYBOARD: Keyboard = Keyboard::default();
+}
+
+impl From<[char; 30]> for FastLayout {
+    fn from(layout: [char; 30]) -> Self {
+		let shape = LayoutShape::default();
+		FastLayout::from((layout.to_vec(), shape, DEFAULT_KEYBOARD.fingering(shape)))
+    }
+}
+
+/// A layout on the default keyboard, shaped after how many characters the string has.
 impl TryFrom<&str> for FastLayout {
     type Error = anyhow::Error;
 
     fn try_from(layout_str: &str) -> Result<Self, Self::Error> {  
-        let mut new_layout = FastLayout::new();
-
-		if layout_str.chars().count() == 30 {
-			for (i, c) in layout_str.chars().enumerate() {
-				new_layout.matrix[i] = c;
-				new_layout.char_to_finger.insert(c, COL_TO_FINGER[i%10]);
-			}
+		let len = layout_str.chars().count();
 
-			Ok(new_layout)
+		if let Some(shape) = LayoutShape::from_len(len) {
+			DEFAULT_KEYBOARD.fits(shape)?;
+			Ok(FastLayout::from((layout_str.chars().collect(), shape, DEFAULT_KEYBOARD.fingering(shape))))
 		} else {
-			anyhow::bail!("string to create a layout should be 30 chars long")
+			anyhow::bail!("string to create a layout should be 30, 33, 36, 40, 44 or 48 chars long, not {len}")
 		}
     }
 }
 
 impl std::fmt::Display for FastLayout {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let mut res = String::new();
+		let mut res = self.matrix_lines(&self.matrix, |_| false).join("\n");
 
-        for (i, c) in self.matrix.iter().enumerate() {
-			if i % 10 == 0 && i > 0 {
-				res.push('\n');
-			}
-			if (i + 5) % 10 == 0 {
-				res.push(' ');
-			}
-			res.push(*c);
-			res.push(' ');
-		};
+		for layer in &self.layers {
+			res.push_str(&format!("\n\nlayer {} {}\n", layer.name, layer.key));
+			res.push_str(&self.matrix_lines(&layer.matrix, |_| false).join("\n"));
+		}
+		if !self.combos.is_empty() {
+			res.push('\n');
+		}
+		for combo in &self.combos {
+			let show = |c: char| if c == ' ' { '_' } else { c };
+			let keys = combo.keys.iter().map(|&k| show(self.matrix[k])).join(" ");
+			res.push_str(&format!("\ncombo {} {keys}", show(combo.output)));
+		}
 		write!(f, "{res}")
     }
 }
 
 impl FastLayout {
 	pub fn layout_str(&self) -> String {
-		String::from_iter(self.matrix)
+		String::from_iter(&self.matrix)
+	}
+
+	/// The rows of a matrix the way they're written in .kb files, with keys for which `marked`
+	/// is true followed by a `*`.
+	fn matrix_lines(&self, matrix: &[char], marked: impl Fn(usize) -> bool) -> Vec<String> {
+		let mut res = Vec::new();
+		let cols = self.shape.cols;
+		let grid_len = self.shape.grid_len();
+		// space is shown as _, the same way it's written in .kb files
+		let show = |i: usize| {
+			let c = if matrix[i] == ' ' { '_' } else { matrix[i] };
+			format!("{c}{}", if marked(i) { '*' } else { ' ' })
+		};
+
+		for row in 0..self.shape.rows {
+			let mut line = String::new();
+			for i in row * cols..(row + 1) * cols {
+				if i % cols == self.shape.hand_split() {
+					line.push(' ');
+				}
+				line.push_str(&show(i));
+			}
+			res.push(line);
+		}
+
+		if self.shape.thumbs > 0 {
+			let left = self.shape.thumbs / 2;
+			let mut line = "  ".repeat(self.shape.hand_split() - left);
+
+			line.extend((grid_len..grid_len + left).map(show));
+			line.push(' ');
+			line.extend((grid_len + left..matrix.len()).map(show));
+			res.push(line);
+		}
+		res
+	}
+
+	/// Both layouts next to each other, with every key that moved marked by a `*`.
+	pub fn diff(&self, other: &FastLayout) -> Option<String> {
+		if self.shape != other.shape {
+			return None
+		}
+		let moved = |i: usize| self.matrix[i] != other.matrix[i];
+		let left = self.matrix_lines(&self.matrix, moved);
+		let right = other.matrix_lines(&other.matrix, moved);
+		let width = left.iter().map(|l| l.chars().count()).max().unwrap_or_default();
+
+		let res = left.iter().zip(&right)
+			.map(|(l, r)| format!("{l:<width$}   {r}"))
+			.join("\n");
+		Some(res)
+	}
+
+	/// Rearranges the keys, and the layers with them, taking every key from `permutation[i]`. The
+	/// result isn't scored.
+	fn permuted(&self, permutation: &[usize]) -> FastLayout {
+		let permute = |matrix: &[char]| permutation.iter().map(|&i| matrix[i]).collect::<Vec<_>>();
+		let fingering = self.fingering.clone();
+		let layers = self.layers.iter()
+			.map(|layer| Layer { matrix: permute(&layer.matrix), ..layer.clone() })
+			.collect();
+
+		// combos stay on the same keys, which are somewhere else now
+		let combos = self.combos.iter()
+			.map(|combo| Combo {
+				keys: combo.keys.iter()
+					.map(|&k| permutation.iter().position(|&from| from == k).unwrap())
+					.collect(),
+				output: combo.output
+			})
+			.collect();
+
+		FastLayout::from((permute(&self.matrix), self.shape, fingering))
+			.with_layers(layers)
+			.with_combos(combos)
+	}
+
+	fn key_str(&self) -> String {
+		let mut res = self.layout_str();
+		for layer in &self.layers {
+			res.extend(&layer.matrix);
+		}
+		for combo in &self.combos {
+			res.push(combo.output);
+			res.extend(combo.keys.iter().map(|&k| self.matrix[k]));
+		}
+		res
+	}
+
+	/// The same layout for every layout that can be turned into this one with `symmetries`, which
+	/// is the variant whose keys come first when sorted. It isn't scored, see `Symmetry::check` for
+	/// when it scores the same as this layout.
+	pub fn canonical(&self, symmetries: &[Symmetry]) -> FastLayout {
+		let generators = symmetries.iter()
+			.flat_map(|s| s.permutations(self.shape))
+			.collect::<Vec<_>>();
+
+		let mut found = vec![(0..self.matrix.len()).collect::<Vec<_>>()];
+		let mut i = 0;
+		while i < found.len() {
+			for generator in &generators {
+				let next = generator.iter().map(|&j| found[i][j]).collect::<Vec<_>>();
+				if !found.contains(&next) {
+					found.push(next);
+				}
+			}
+			i += 1;
+		}
+
+		found.iter()
+			.map(|permutation| self.permuted(permutation))
+			.min_by_key(|layout| layout.key_str())
+			.unwrap()
+	}
+
+	/// Where every key of this layout ends up on `other`, or `None` if the two don't have the same
+	/// shape and keys.
+	pub fn permutation_to(&self, other: &FastLayout) -> Option<Vec<usize>> {
+		if self.shape != other.shape {
+			return None
+		}
+		let mut positions = FxHashMap::<char, Vec<usize>>::default();
+		for (i, &c) in other.matrix.iter().enumerate().rev() {
+			positions.entry(c).or_default().push(i);
+		}
+		// keys that are already in place keep their position when a character is on the layout twice
+		let mut res = vec![usize::MAX; self.matrix.len()];
+		for (i, &c) in self.matrix.iter().enumerate() {
+			if other.matrix[i] == c {
+				let targets = positions.get_mut(&c)?;
+				targets.retain(|&t| t != i);
+				res[i] = i;
+			}
+		}
+		for (i, &c) in self.matrix.iter().enumerate() {
+			if res[i] == usize::MAX {
+				res[i] = positions.get_mut(&c)?.pop()?;
+			}
+		}
+		Some(res)
+	}
+
+	/// The cycles of the permutation to `other`, leaving out keys that don't move. The key at each
+	/// position of a cycle moves to the next one.
+	pub fn cycles_to(&self, other: &FastLayout) -> Option<Vec<Vec<usize>>> {
+		let permutation = self.permutation_to(other)?;
+		let mut seen = vec![false; permutation.len()];
+		let mut res = Vec::new();
+
+		for start in 0..permutation.len() {
+			if seen[start] || permutation[start] == start {
+				continue
+			}
+			let mut cycle = Vec::new();
+			let mut i = start;
+			while !seen[i] {
+				seen[i] = true;
+				cycle.push(i);
+				i = permutation[i];
+			}
+			res.push(cycle);
+		}
+		Some(res)
+	}
+
+	/// The fewest swaps that turn this layout into `other`, one less than the length of every cycle.
+	pub fn swaps_to(&self, other: &FastLayout) -> Option<Vec<PosPair>> {
+		let res = self.cycles_to(other)?
+			.into_iter()
+			.flat_map(|cycle| {
+				let first = cycle[0];
+				cycle.into_iter().skip(1).map(move |i| PosPair(first, i))
+			})
+			.collect();
+		Some(res)
+	}
+
+	pub fn with_layers(mut self, layers: Vec<Layer>) -> Self {
+		for layer in &layers {
+			assert_eq!(layer.matrix.len(), self.matrix.len(), "layer {} doesn't match the layout size", layer.name);
+		}
+		self.layers = layers;
+		self
+	}
+
+	pub fn with_combos(mut self, combos: Vec<Combo>) -> Self {
+		for combo in &combos {
+			assert!(combo.keys.iter().all(|&k| k < self.matrix.len()), "combo {} isn't on the layout", combo.output);
+		}
+		self.combos = combos;
+		self
+	}
+
+	/// Swaps what combo `combo` types with the character on key `i`.
+	pub fn swap_combo(&mut self, combo: usize, i: usize) {
+		std::mem::swap(&mut self.combos[combo].output, &mut self.matrix[i]);
+		let (removed, added) = (self.combos[combo].output, self.matrix[i]);
+
+		self.char_to_finger.remove(&removed);
+		self.char_to_key.remove(&removed);
+		self.char_to_finger.insert(added, self.fingering[i]);
+		self.char_to_key.insert(added, i);
+	}
+
+	/// The layer key and base layer key to press for every character that is only on a layer.
+	pub fn layer_keys(&self) -> FxHashMap<char, [char; 2]> {
+		let mut res = FxHashMap::default();
+
+		for layer in &self.layers {
+			for (&c, &base) in layer.matrix.iter().zip(&self.matrix) {
+				if c != ' ' && self.char_to_finger.get(&c).is_none() {
+					res.entry(c).or_insert([layer.key, base]);
+				}
+			}
+		}
+		res
 	}
 }
 
 impl Layout<char> for FastLayout {
-	fn new() -> FastLayout {
+	fn new(shape: LayoutShape, fingering: Fingering) -> FastLayout {
+		assert_eq!(fingering.len(), shape.len(), "fingering doesn't match the layout size");
+
 		FastLayout {
-			matrix: ['.'; 30],
+			matrix: vec!['.'; shape.len()],
 			char_to_finger: CharToFinger::default(),
+			char_to_key: CharToFinger::default(),
+			fingering,
+			layers: Vec::new(),
+			combos: Vec::new(),
+			shape,
 			score: 0.0
 		}
 	}
 
-	fn random(mut with_chars: [char; 30]) -> FastLayout {
-		shuffle_pins::<30, char>(&mut with_chars, &[]);
-		FastLayout::from(with_chars)
+	fn random(mut with_chars: Vec<char>, shape: LayoutShape, fingering: Fingering) -> FastLayout {
+		shuffle_pins::<MAX_KEYS, char>(&mut with_chars, &[]);
+		FastLayout::from((with_chars, shape, fingering))
 	}
 
-	fn random_pins(mut layout_chars: [char; 30], pins: &[usize]) -> FastLayout {
-		shuffle_pins::<30, char>(&mut layout_chars, pins);
-		FastLayout::from(layout_chars)
+	fn random_pins(mut layout_chars: Vec<char>, pins: &[usize], shape: LayoutShape, fingering: Fingering) -> FastLayout {
+		shuffle_pins::<MAX_KEYS, char>(&mut layout_chars, pins);
+		FastLayout::from((layout_chars, shape, fingering))
 	}
 
 	#[inline(always)]
@@ -127,21 +558,26 @@ impl Layout<char> for FastLayout {
 
 	#[inline]
 	fn char(&self, x: usize, y: usize) -> char {
-		assert!(x < 10 && y < 3);
-		self.matrix[x + 10*y]
+		assert!(x < self.shape.cols && y < self.shape.rows);
+		self.matrix[x + self.shape.cols*y]
 	}
 
 	fn swap(&mut self, i1: usize, i2: usize) -> Option<()> {
-		if i1 < 30 && i2 < 30 {
+		if i1 < self.matrix.len() && i2 < self.matrix.len() {
 
 			let char1 = self.matrix[i1];
 			let char2 = self.matrix[i2];
 
 			self.matrix[i1] = char2;
 			self.matrix[i2] = char1;
-			self.char_to_finger.insert(char1, COL_TO_FINGER[i2 % 10]);
-			self.char_to_finger.insert(char2, COL_TO_FINGER[i1 % 10]);
+			self.char_to_finger.insert(char1, self.fingering[i2]);
+			self.char_to_finger.insert(char2, self.fingering[i1]);
+			self.char_to_key.insert(char1, i2);
+			self.char_to_key.insert(char2, i1);
 
+			for layer in self.layers.iter_mut() {
+				layer.matrix.swap(i1, i2);
+			}
 			return Some(())
 		} else {
 			println!("Invalid coordinate, swap was cancelled");
@@ -157,8 +593,14 @@ impl Layout<char> for FastLayout {
 		*self.matrix.get_unchecked_mut(i1) = char2;
 		*self.matrix.get_unchecked_mut(i2) = char1;
 
-		self.char_to_finger.insert(char1, COL_TO_FINGER[i2 % 10]);
-		self.char_to_finger.insert(char2, COL_TO_FINGER[i1 % 10]);
+		self.char_to_finger.insert(char1, *self.fingering.get_unchecked(i2));
+		self.char_to_finger.insert(char2, *self.fingering.get_unchecked(i1));
+		self.char_to_key.insert(char1, i2);
+		self.char_to_key.insert(char2, i1);
+
+		for layer in self.layers.iter_mut() {
+			layer.matrix.swap(i1, i2);
+		}
 	}
 
 	#[inline(always)]
@@ -171,25 +613,29 @@ impl Layout<char> for FastLayout {
 		self.swap_xy_no_bounds(pair.0, pair.1);
 	}
 
+	/// Only swaps the keys in the grid, thumb keys are left alone.
 	unsafe fn swap_cols_no_bounds(&mut self, col1: usize, col2: usize) {
-		self.swap_xy_no_bounds(col1, col2);
-		self.swap_xy_no_bounds(col1 + 10, col2 + 10);
-		self.swap_xy_no_bounds(col1 + 20, col2 + 20);
+		for row in 0..self.shape.rows {
+			let start = row * self.shape.cols;
+			self.swap_xy_no_bounds(start + col1, start + col2);
+		}
 	}
 
 	fn swap_indexes(&mut self) {
+		let offset = self.shape.col_offset();
 		unsafe {
-			self.swap_cols_no_bounds(3, 6);
-			self.swap_cols_no_bounds(4, 5);
+			self.swap_cols_no_bounds(offset + 3, offset + 6);
+			self.swap_cols_no_bounds(offset + 4, offset + 5);
 		}
 	}
 
-	fn get_index(&self, index: usize) -> [char; 6] {
-		let mut new_index = [' '; 6];
-		let start_pos = index*2 + 3;
+	fn get_index(&self, index: usize) -> Vec<char> {
+		let rows = self.shape.rows;
+		let mut new_index = vec![' '; 2*rows];
+		let start_pos = index*2 + 3 + self.shape.col_offset();
 		for i in 0..2 {
-			for j in 0..3 {
-				new_index[2*j + i] = self.matrix[start_pos + i + 10*j];
+			for j in 0..rows {
+				new_index[2*j + i] = self.matrix[start_pos + i + self.shape.cols*j];
 			}
 		}
 		new_index
@@ -202,8 +648,8 @@ impl Layout<char> for FastLayout {
 		if (a | b | c) == usize::MAX {
 			return TrigramPattern::Invalid
 		}
-		// a, b and c are numbers between 0 and 7. This means they fit in exactly 3 bits (7 == 0b111)
-		let combination = (a << 6) | (b << 3) | c;
+		// a, b and c are numbers between 0 and 9. This means they fit in 4 bits (15 == 0b1111)
+		let combination = (a << 8) | (b << 4) | c;
 		TRIGRAM_COMBINATIONS[combination]
 	}
 
@@ -211,8 +657,8 @@ impl Layout<char> for FastLayout {
 		let a = *self.char_to_finger.get(&trigram[0]).unwrap_unchecked();
 		let b = *self.char_to_finger.get(&trigram[1]).unwrap_unchecked();
 		let c = *self.char_to_finger.get(&trigram[2]).unwrap_unchecked();
-		// a, b and c are numbers between 0 and 7. This means they fit in exactly 3 bits (7 == 0b111)
-		let combination = (a << 6) | (b << 3) | c;
+		// a, b and c are numbers between 0 and 9. This means they fit in 4 bits (15 == 0b1111)
+		let combination = (a << 8) | (b << 4) | c;
 		TRIGRAM_COMBINATIONS[combination]
 	}
 }
@@ -249,6 +695,8 @@ mod tests {
 		let mut qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
 		unsafe { qwerty.swap_xy_no_bounds(9, 12) };
 		assert_eq!(qwerty.layout_str(), "qwertyuiodaspfghjkl;zxcvbnm,./".to_string());
+		assert_eq!(qwerty.char_to_key.get(&'p'), Some(&12usize));
+		assert_eq!(qwerty.char_to_key.get(&'d'), Some(&9usize));
 	}
 
 	#[test]
@@ -281,7 +729,9 @@ mod tests {
 		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
 		assert_eq!(qwerty.char_to_finger.get(&'a'), Some(&0usize));
 		assert_eq!(qwerty.char_to_finger.get(&'w'), Some(&1usize));
-		assert_eq!(qwerty.char_to_finger.get(&'c'), Some(&2usize));
+		// the default keyboard is angle mod, which moves the bottom left keys one finger inwards
+		assert_eq!(qwerty.char_to_finger.get(&'z'), Some(&1usize));
+		assert_eq!(qwerty.char_to_finger.get(&'c'), Some(&3usize));
 
 		assert_eq!(qwerty.char_to_finger.get(&'r'), Some(&3usize));
 		assert_eq!(qwerty.char_to_finger.get(&'b'), Some(&3usize));
@@ -310,6 +760,221 @@ mod tests {
 		assert_eq!(qwerty.c(22), 'c');
 	}
 
+	#[test]
+	fn bigger_shapes() {
+		let qwerty = FastLayout::try_from("[qwertyuiop]-asdfghjkl;'=zxcvbnm,./\\")
+			.expect("couldn't create 3x12 qwerty");
+		
+		assert_eq!(qwerty.shape, LayoutShape::new(3, 12));
+		assert_eq!(qwerty.char(1, 1), 'a');
+		assert_eq!(qwerty.char_to_finger.get(&'['), Some(&0usize));
+		assert_eq!(qwerty.char_to_finger.get(&'a'), Some(&0usize));
+		assert_eq!(qwerty.char_to_finger.get(&'b'), Some(&3usize));
+		assert_eq!(qwerty.char_to_finger.get(&'\\'), Some(&7usize));
+		assert_eq!(qwerty.get_index(0), vec!['r', 't', 'f', 'g', 'v', 'b']);
+
+		let numrow = FastLayout::try_from("1234567890qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		assert_eq!(numrow.shape, LayoutShape::new(4, 10));
+		assert_eq!(numrow.char_to_finger.get(&'5'), Some(&3usize));
+		assert_eq!(numrow.char(0, 2), 'a');
+
+		assert!(FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.").is_err());
+	}
+
+	#[test]
+	fn thumb_keys() {
+		let shape = LayoutShape::try_from("3x10+2").unwrap();
+		let chars = "qwertyuiopasdfghjkl;zxcvbnm,./1 ".chars().collect();
+		let layout = FastLayout::from((chars, shape, shape.default_fingering()));
+
+		assert_eq!(layout.char_to_finger.get(&'1'), Some(&LEFT_THUMB));
+		assert_eq!(layout.char_to_finger.get(&' '), Some(&RIGHT_THUMB));
+		assert_eq!(
+			layout.to_string().lines().last(),
+			Some("        1  _ ")
+		);
+	}
+
+	#[test]
+	fn custom_fingering() {
+		let shape = LayoutShape::default();
+		let mut fingering = shape.default_fingering();
+		fingering[24] = 4;
+
+		let chars = "qwertyuiopasdfghjkl;zxcvbnm,./".chars().collect();
+		let mut qwerty = FastLayout::from((chars, shape, fingering));
+		
+		assert_eq!(qwerty.char_to_finger.get(&'b'), Some(&4usize));
+		qwerty.swap(24, 0);
+		assert_eq!(qwerty.char_to_finger.get(&'q'), Some(&4usize));
+		assert_eq!(qwerty.char_to_finger.get(&'b'), Some(&0usize));
+	}
+
+	#[test]
+	fn layers() {
+		let kb = "q w e r t  y u i o p\na s d f g  h j k l ;\nz x c v b  n m , . ^\n\n\
+			layer shift ^\nQ W E R T  Y U I O P\nA S D F G  H J K L :\nZ X C V B  N M < > _\n\n\
+			layer sym ^\n_ _ _ _ _  _ _ _ _ _\n{ } ( ) /  _ _ _ _ _\n_ _ _ _ _  _ _ _ _ _";
+
+		let layers = layers_from_kb(kb, LayoutShape::default()).unwrap();
+		assert_eq!(layers.len(), 2);
+		assert_eq!(layers[0].key, '^');
+		assert_eq!(layers[1].matrix[14], '/');
+		assert_eq!(layers[1].matrix[0], ' ');
+		assert!(layers_from_kb("layer shift\nq w e", LayoutShape::default()).is_err());
+
+		let mut layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.^")
+			.unwrap()
+			.with_layers(layers);
+		assert_eq!(layout.layer_keys().get(&'Q'), Some(&['^', 'q']));
+		assert_eq!(layout.layer_keys().get(&'/'), Some(&['^', 'g']));
+		assert_eq!(layout.layer_keys().get(&' '), None);
+
+		layout.swap(0, 14);
+		assert_eq!(layout.layers[0].matrix[0], 'G');
+		assert_eq!(layout.layer_keys().get(&'/'), Some(&['^', 'g']));
+
+		let shown = layout.to_string();
+		assert_eq!(layers_from_kb(&shown, layout.shape).unwrap(), layout.layers);
+	}
+
+	#[test]
+	fn combos() {
+		let kb = "q w e r t  y u i o p\na s d f g  h j k l ;\nz x c v b  n m , . /\n\ncombo ' w e\ncombo - x c";
+		let mut layout = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let combos = combos_from_kb(kb, &layout.matrix).unwrap();
+		assert_eq!(combos, vec![
+			Combo { keys: vec![1, 2], output: '\'' },
+			Combo { keys: vec![21, 22], output: '-' }
+		]);
+
+		for wrong in ["combo ; w", "combo [ w 7", "combo ab w e", "combo [ w w"] {
+			assert!(combos_from_kb(wrong, &layout.matrix).is_err());
+		}
+
+		layout = layout.with_combos(combos);
+		let shown = layout.to_string();
+		assert!(shown.ends_with("\n\ncombo ' w e\ncombo - x c"));
+		assert_eq!(combos_from_kb(&shown, &layout.matrix).unwrap(), layout.combos);
+
+		let mirrored = layout.canonical(&[Symmetry::Mirror]);
+		let keys = mirrored.combos[0].keys.iter().map(|&k| mirrored.matrix[k]).collect::<Vec<_>>();
+		assert_eq!(keys, vec!['w', 'e']);
+
+		layout.swap_combo(0, 0);
+		assert_eq!(layout.matrix[0], '\'');
+		assert_eq!(layout.combos[0].output, 'q');
+		assert_eq!(layout.char_to_finger.get(&'\''), Some(&0));
+		assert_eq!(layout.char_to_finger.get(&'q'), None);
+	}
+
+	#[test]
+	fn swaps_to() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let dvorak = FastLayout::try_from("/,.pyfgcrlaoeuidhtns;qjkxbmwvz").unwrap();
+
+		let cycles = qwerty.cycles_to(&dvorak).unwrap();
+		let swaps = qwerty.swaps_to(&dvorak).unwrap();
+		let moved = cycles.iter().map(|c| c.len()).sum::<usize>();
+		assert_eq!(swaps.len(), moved - cycles.len());
+
+		let mut layout = qwerty.clone();
+		for swap in &swaps {
+			layout.swap_pair(swap);
+		}
+		assert_eq!(layout.matrix, dvorak.matrix);
+
+		assert_eq!(qwerty.swaps_to(&qwerty), Some(Vec::new()));
+		let colemak = FastLayout::try_from("qwfpgjluy;arstdhneiozxcvbkm,./").unwrap();
+		assert_eq!(qwerty.swaps_to(&colemak).unwrap().len(), 17 - 2);
+		
+		let numrow = FastLayout::try_from("1234567890qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		assert!(qwerty.swaps_to(&numrow).is_none());
+		let doubled = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,.q").unwrap();
+		assert!(qwerty.swaps_to(&doubled).is_none());
+	}
+
+	#[test]
+	fn diff() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let mut swapped = qwerty.clone();
+		swapped.swap(0, 11);
+
+		let diff = qwerty.diff(&swapped).unwrap();
+		let first = diff.lines().next().unwrap();
+		assert_eq!(first, "q*w e r t  y u i o p    s*w e r t  y u i o p ");
+		assert_eq!(diff.matches('*').count(), 4);
+	}
+
+	#[test]
+	fn canonical() {
+		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
+		let mut mirrored = FastLayout::try_from("poiuytrewq;lkjhgfdsa/.,mnbvcxz").unwrap();
+		let mut index_swapped = qwerty.clone();
+		index_swapped.swap_indexes();
+		
+		let canonical = qwerty.canonical(&[Symmetry::Mirror]);
+		assert_eq!(mirrored.canonical(&[Symmetry::Mirror]).matrix, canonical.matrix);
+		// the index columns on the other hand are on other fingers, so that's another layout
+		assert_ne!(index_swapped.canonical(&[Symmetry::Mirror]).matrix, canonical.matrix);
+		assert_eq!(qwerty.canonical(&[]).matrix, qwerty.matrix);
+
+		unsafe { mirrored.swap_cols_no_bounds(5, 6) };
+		assert_eq!(mirrored.canonical(&[Symmetry::Mirror, Symmetry::IndexOrder]).matrix, qwerty.canonical(&[Symmetry::Mirror, Symmetry::IndexOrder]).matrix);
+
+		let mut scored = qwerty.clone();
+		scored.score = 1.0;
+		assert_eq!(scored.canonical(&[Symmetry::Mirror]).score, 0.0);
+
+		// how far left q is, so the canonical form scores differently than qwerty
+		let score = |l: &FastLayout| -(l.matrix.iter().position(|&c| c == 'q').unwrap() as f64);
+		let distinct = distinct_layouts(
+			[qwerty.clone(), mirrored, scored, index_swapped],
+			&[Symmetry::Mirror, Symmetry::IndexOrder],
+			score
+		);
+		assert_eq!(distinct.len(), 2);
+		assert_eq!(distinct[0].1, 3);
+		assert_eq!(distinct[0].0.score, score(&distinct[0].0));
+		assert_eq!(distinct[1].1, 1);
+
+		// q and a taking each other's place
+		let qa = [Symmetry::Custom([10].into_iter().chain(1..10).chain([0]).chain(11..30).collect())];
+		let mut swapped = qwerty.clone();
+		swapped.swap(0, 10);
+		assert_eq!(swapped.canonical(&qa).matrix, qwerty.canonical(&qa).matrix);
+		assert_eq!(distinct_layouts([qwerty.clone(), swapped], &qa, |_| 0.0).len(), 1);
+	}
+
+	#[test]
+	fn symmetry_check() {
+		use crate::keyboard::Keyboard;
+
+		let shape = LayoutShape::default();
+		let check = |symmetry: Symmetry, keyboard: &str| {
+			let keyboard = Keyboard::load(keyboard).unwrap();
+			symmetry.check(shape, &keyboard.fingering(shape), &get_effort_map(1.0, &keyboard, shape))
+		};
+		assert!(check(Symmetry::Mirror, "ortho").is_ok());
+		// the inner column is harder to reach than the index column
+		assert!(check(Symmetry::IndexOrder, "ortho").is_err());
+		// the angle mod changes the fingering of the left hand
+		assert!(check(Symmetry::Mirror, "ansi angle").is_err());
+
+		let odd = LayoutShape::new(3, 11);
+		assert!(Symmetry::Mirror.check(odd, &odd.default_fingering(), &vec![1.0; odd.len()]).is_err());
+
+		// the top and bottom row swapped keeps every key on its finger
+		let rows = Symmetry::Custom((20..30).chain(10..20).chain(0..10).collect());
+		let flat = |symmetry: &Symmetry| symmetry.check(shape, &shape.default_fingering(), &[1.0; 30]);
+		assert!(flat(&rows).is_ok());
+		assert!(Symmetry::ALL.iter().any(|symmetry| *symmetry != Symmetry::Mirror && flat(symmetry).is_ok()));
+		assert!(check(rows, "ortho").is_err());
+		assert!(flat(&Symmetry::Custom((0..29).collect())).is_err());
+		assert!(flat(&Symmetry::Custom((0..29).chain([0]).collect())).is_err());
+		assert!(flat(&Symmetry::Custom((1..30).chain([0]).collect())).is_err());
+	}
+
 	#[test]
 	fn thing() {
 		let qwerty = FastLayout::try_from("qwertyuiopasdfghjkl;zxcvbnm,./").unwrap();
@@ -8,10 +8,15 @@ pub mod load_text;
 pub mod layout;
 pub mod trigram_patterns;
 pub mod utility;
+pub mod keyboard;
 pub mod weights;
+pub mod constraints;
+pub mod travel;
 pub mod generate;
 pub mod translation;
 pub mod languages_cfg;
+pub mod export;
+pub mod import;
 
 pub use rayon;
 pub use serde;
\ No newline at end of file
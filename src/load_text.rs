@@ -19,6 +19,14 @@ pub fn load_raw(language: &str) {
     load_data(language, Translator::raw(true)).unwrap();
 }
 
+/// Loads data that keeps space as a key, saved as `{language}_space`. It isn't shipped, and is only
+/// comparable with the data without space when loaded from the same text.
+pub fn load_with_space(language: &str) -> Result<()> {
+    let translator = Translator::language_or_raw(language)
+        + Translator::new().keep_space(true).build();
+    load_data(language, translator)
+}
+
 #[allow(dead_code)]
 pub(crate) fn load_default(language: &str) {
     let translator = Translator::language_or_raw(language);
@@ -43,9 +51,25 @@ pub(crate) fn load_all_default() -> Result<()> {
     Ok(())
 }
 
+/// Turns every run of whitespace into a single space, as it's a single press of the space key.
+fn collapse_spaces(s: &str) -> SmartString<LazyCompact> {
+    let mut res = SmartString::new();
+    let mut after_space = false;
+
+    for c in s.chars() {
+        let is_space = c.is_whitespace();
+        if !(is_space && after_space) {
+            res.push(if is_space { ' ' } else { c });
+        }
+        after_space = is_space;
+    }
+    res
+}
+
 pub fn load_data(language: &str, translator: Translator) -> Result<()> {
     let start_total = Instant::now();
     let is_raw = translator.is_raw;
+    let keep_space = translator.keep_space;
 
     let chunkers = read_dir(format!("static/text/{language}"))?
         .filter_map(Result::ok)
@@ -66,9 +90,11 @@ pub fn load_data(language: &str, translator: Translator) -> Result<()> {
                 Make sure all files in the directory are valid utf-8."
             )
         )
-        .map(|s|
+        .map(|s| if keep_space {
+            TextNgrams::<5>::from(collapse_spaces(s).as_str())
+        } else {
             TextNgrams::<5>::from(s)
-        )
+        })
         .reduce(
             || TextNgrams::default(),
             |accum, new| accum.combine_with(new)
@@ -120,6 +146,8 @@ pub struct TextData {
     skipgrams3: IndexMap<SmartString<LazyCompact>, f64>,
     trigrams: IndexMap<SmartString<LazyCompact>, f64>,
 
+    #[serde(skip)]
+    keep_space: bool,
     #[serde(skip)]
     char_sum: f64,
     #[serde(skip)]
@@ -168,11 +196,24 @@ impl TextData {
 
 impl From<(TextNgrams<5>, &str, Translator)> for TextData {
     fn from((ngrams, language, translator): (TextNgrams<5>, &str, Translator)) -> Self {
-        let mut res = TextData::new(language);
+        let mut res = if translator.keep_space {
+            TextData::new(&format!("{language}_space"))
+        } else {
+            TextData::new(language)
+        };
+        res.keep_space = translator.keep_space;
 
         for (quingram, freq) in ngrams.ngrams.into_iter() {
             let q = SmartString::<LazyCompact>::from_iter(quingram);
-            let translated = translator.translate(q.as_str());
+            let mut translated = translator.translate(q.as_str());
+            if res.keep_space {
+                // characters translated to spaces can make runs of them as well. a run is counted
+                // from the window starting at its last space only
+                if translated.starts_with("  ") {
+                    continue
+                }
+                translated = collapse_spaces(&translated);
+            }
             let first_char = quingram[0];
             
             let it_count = if let Some(f) = translator.table.get(&first_char) {
@@ -234,25 +275,31 @@ impl TextData {
     }
 
     fn add_from_n_subsequent<const N: usize>(&mut self, ngram: [char; N], freq: f64) {
-        if N > 0 && let c1 = ngram[0] && c1 != ' ' {
+        // runs of spaces are collapsed into one before this when keeping them
+        let keep_space = self.keep_space;
+        let is_key = |i: usize| ngram[i] != ' ' || keep_space;
+
+        if N > 0 && is_key(0) {
+            let c1 = ngram[0];
             self.add_character(c1, freq);
             // take first, first 2 etc chars of the trigram every time for the appropriate stat
-            // as long as they don't contain spaces. return `c2` so I don't iter.next() too much
-            let c2 = if N > 1 && let c2 = ngram[1] && c2 != ' ' {
-                self.add_bigram([c1, c2], freq);
-                c2
-            } else { ' ' };
+            // as long as they are keys. return `c2` so I don't iter.next() too much
+            let c2 = if N > 1 && is_key(1) {
+                self.add_bigram([c1, ngram[1]], freq);
+                Some(ngram[1])
+            } else { None };
             // c1 and c3 for skipgrams
-            if N > 2 && let c3 = ngram[2] && c3 != ' ' {
+            if N > 2 && is_key(2) {
+                let c3 = ngram[2];
                 self.add_skipgram([c1, c3], freq);
 
-                if c2 != ' ' { self.add_trigram([c1, c2, c3], freq); }
+                if let Some(c2) = c2 { self.add_trigram([c1, c2, c3], freq); }
 
-                if N > 3 && let c4 = ngram[3] && c4 != ' ' {
-                    self.add_skipgram2([c1, c4], freq);
+                if N > 3 && is_key(3) {
+                    self.add_skipgram2([c1, ngram[3]], freq);
 
-                    if N > 4 && let c5 = ngram[4] && c5 != ' ' {
-                        self.add_skipgram3([c1, c5], freq);
+                    if N > 4 && is_key(4) {
+                        self.add_skipgram3([c1, ngram[4]], freq);
                     }
                 }
             }
@@ -367,6 +414,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn from_textngrams_with_space() {
+        let mut ngrams = TextNgrams::<5>::default();
+        ngrams.ngrams.insert(['a', ' ', 'b', ' ', ' '], 1);
+        ngrams.ngrams.insert([' ', 'b', ' ', ' ', 'c'], 1);
+        ngrams.ngrams.insert(['b', ' ', ' ', 'c', ' '], 1);
+        ngrams.ngrams.insert([' ', ' ', 'c', ' ', ' '], 1);
+        let translator = Translator::new()
+            .letters_to_lowercase("abc")
+            .keep_space(true)
+            .build();
+        let data = TextData::from((ngrams, "abc", translator));
+
+        assert_eq!(data.language, "abc_space");
+        assert_eq!(data.char_sum, 3.0);
+        assert_eq!(data.characters.get(&' '), Some(&(1.0/3.0)));
+        assert_eq!(data.bigrams.get(&SmartString::from(" b")), Some(&(1.0/3.0)));
+        assert_eq!(data.bigrams.get(&SmartString::from("  ")), None);
+        assert_eq!(data.trigrams.get(&SmartString::from("b  ")), None);
+        assert_eq!(data.trigrams.get(&SmartString::from(" b ")), Some(&(1.0/3.0)));
+        // the double space between b and c is a single key press
+        assert_eq!(data.trigrams.get(&SmartString::from("b c")), Some(&(1.0/3.0)));
+        assert_eq!(data.skipgrams.get(&SmartString::from("bc")), Some(&(1.0/3.0)));
+        assert_eq!(collapse_spaces("a  b\n\tc "), "a b c ");
+    }
+
 	#[test]
 	fn load_language_data() {
         use language_data::*;
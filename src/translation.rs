@@ -6,6 +6,8 @@ use fxhash::FxHashMap;
 pub struct Translator {
     pub table: FxHashMap<char, SmartString<Compact>>,
     pub is_raw: bool,
+    /// Whether space is kept as a key in n-grams, for layouts with a space thumb key.
+    pub keep_space: bool,
     pub(crate) is_empty: bool
 }
 
@@ -24,6 +26,7 @@ impl std::ops::Add for Translator {
     ///the table of the FIRST translator takes priority over the SECOND.
     fn add(mut self, rhs: Self) -> Self::Output {
         self.is_raw |= rhs.is_raw;
+        self.keep_space |= rhs.keep_space;
         self.is_empty &= rhs.is_empty;
 
         if !self.is_empty {
@@ -46,7 +49,8 @@ impl Translator {
     pub fn new() -> TranslatorBuilder {
         TranslatorBuilder {
             table: FxHashMap::default(),
-            is_raw: false
+            is_raw: false,
+            keep_space: false
         }
     }
 
@@ -97,10 +101,16 @@ impl Translator {
 
 pub struct TranslatorBuilder {
     table: FxHashMap<char, SmartString<Compact>>,
-    is_raw: bool
+    is_raw: bool,
+    keep_space: bool
 }
 
 impl TranslatorBuilder {
+    pub fn keep_space(&mut self, keep_space: bool) -> &mut Self {
+        self.keep_space = keep_space;
+        self
+    }
+
     pub fn to_nothing(&mut self, to_nothing: &str) -> &mut Self {
         for c in to_nothing.chars() {
             self.table.insert(c, SmartString::<Compact>::from(""));
@@ -392,7 +402,8 @@ impl TranslatorBuilder {
         Translator {
             is_empty: self.table.len() == 0,
             table: std::mem::take(&mut self.table),
-            is_raw: self.is_raw
+            is_raw: self.is_raw,
+            keep_space: self.keep_space
         }
     }
 }
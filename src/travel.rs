@@ -0,0 +1,206 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::language_data::LanguageData;
+use crate::layout::FastLayout;
+use crate::utility::*;
+use crate::keyboard::Keyboard;
+
+/// Share of the way back to its home key a finger moves every time another finger presses a key.
+pub const DEFAULT_DRIFT: f64 = 0.5;
+
+/// What the effort of every key is based on.
+#[derive(Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EffortModel {
+	/// The hand made heatmap of the keyboard type.
+	#[default]
+	Heatmap,
+	/// How far a finger travels to press the key and back to its home key.
+	Travel
+}
+
+/// How far every finger moved while typing, in key widths.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Travel {
+	pub fingers: [f64; FINGER_COUNT],
+	/// The amount of characters typed, or the total frequency of the n-grams simulated.
+	pub presses: f64
+}
+
+impl Travel {
+	pub fn total(&self) -> f64 {
+		self.fingers.iter().sum()
+	}
+
+	/// Average distance per key press.
+	pub fn per_press(&self) -> f64 {
+		if self.presses > 0.0 { self.total() / self.presses } else { 0.0 }
+	}
+}
+
+impl std::fmt::Display for Travel {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let fingers = self.fingers.iter()
+			.map(|d| format!("{:.3}", d / self.presses.max(f64::EPSILON)))
+			.collect::<Vec<_>>();
+		write!(
+			f, "Travel: {:.3} per key press, {:.1} in total\n    [{}]",
+			self.per_press(), self.total(), fingers.join(", ")
+		)
+	}
+}
+
+/// Simulates where the fingers are while typing. Every finger starts on its home key, moves to the
+/// keys it presses and drifts back home while the other fingers type.
+#[derive(Clone, Debug)]
+pub struct TravelModel {
+	positions: Vec<(f64, f64)>,
+	homes: [(f64, f64); FINGER_COUNT],
+	pub drift: f64
+}
+
+type Fingers = [(f64, f64); FINGER_COUNT];
+
+fn distance((x1, y1): (f64, f64), (x2, y2): (f64, f64)) -> f64 {
+	((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt()
+}
+
+impl TravelModel {
+	pub fn new(keyboard: &Keyboard, shape: LayoutShape, fingering: &[usize]) -> Self {
+		let positions = keyboard.key_positions(shape);
+		let homes = keyboard.home_keys(shape, fingering)
+			.map(|key| key.map(|k| positions[k]).unwrap_or_default());
+
+		Self { positions, homes, drift: DEFAULT_DRIFT }
+	}
+
+	pub fn with_drift(self, drift: f64) -> Self {
+		Self { drift: drift.clamp(0.0, 1.0), ..self }
+	}
+
+	/// Presses a key, adding how far every finger moves to `travel`.
+	fn press(&self, fingers: &mut Fingers, key: usize, finger: usize, travel: &mut [f64; FINGER_COUNT]) {
+		for (f, pos) in fingers.iter_mut().enumerate() {
+			let to = if f == finger {
+				self.positions[key]
+			} else {
+				let home = self.homes[f];
+				(pos.0 + (home.0 - pos.0) * self.drift, pos.1 + (home.1 - pos.1) * self.drift)
+			};
+			travel[f] += distance(*pos, to);
+			*pos = to;
+		}
+	}
+
+	/// Types a text on a layout. Characters that aren't on the layout are skipped.
+	pub fn simulate_text(&self, layout: &FastLayout, text: &str) -> Travel {
+		let mut fingers = self.homes;
+		let mut travel = Travel::default();
+
+		for c in text.chars().flat_map(char::to_lowercase) {
+			if let Some(&key) = layout.char_to_key.get(&c) {
+				self.press(&mut fingers, key, layout.fingering[key], &mut travel.fingers);
+				travel.presses += 1.0;
+			}
+		}
+		travel
+	}
+
+	/// Types every file in a directory like `static/text/{language}`, one after the other.
+	pub fn simulate_files<P: AsRef<Path>>(&self, layout: &FastLayout, text_dir: P) -> Result<Travel> {
+		let mut travel = Travel::default();
+
+		for entry in std::fs::read_dir(text_dir)?.flatten() {
+			let text = std::fs::read_to_string(entry.path())?;
+			let file = self.simulate_text(layout, &text);
+
+			travel.fingers.iter_mut().zip(file.fingers).for_each(|(t, f)| *t += f);
+			travel.presses += file.presses;
+		}
+		Ok(travel)
+	}
+
+	/// Estimates the travel of typing a language from its trigrams. Every trigram is typed from the
+	/// home position, and only its last key press counts, the first two only place the fingers.
+	pub fn simulate_ngrams(&self, layout: &FastLayout, data: &LanguageData) -> Travel {
+		let mut travel = Travel::default();
+
+		for (trigram, freq) in data.trigrams.iter() {
+			let keys = trigram.iter()
+				.filter_map(|c| layout.char_to_key.get(c).copied())
+				.collect::<Vec<_>>();
+			let [k1, k2, k3] = keys[..] else {
+				continue
+			};
+			let mut fingers = self.homes;
+			let mut ignored = [0.0; FINGER_COUNT];
+			self.press(&mut fingers, k1, layout.fingering[k1], &mut ignored);
+			self.press(&mut fingers, k2, layout.fingering[k2], &mut ignored);
+
+			let mut last = [0.0; FINGER_COUNT];
+			self.press(&mut fingers, k3, layout.fingering[k3], &mut last);
+			travel.fingers.iter_mut().zip(last).for_each(|(t, l)| *t += l * freq);
+			travel.presses += freq;
+		}
+		travel
+	}
+
+	/// How far a finger travels to press every key on its own and get back home, which is its
+	/// distance from the home key of its finger there and back.
+	pub fn key_costs(&self, fingering: &[usize]) -> Vec<f64> {
+		self.positions.iter()
+			.zip(fingering)
+			.map(|(&pos, &finger)| 2.0 * distance(pos, self.homes[finger]))
+			.collect()
+	}
+}
+
+/// An effort map from the travel of every key, scaled like `get_effort_map` so home keys cost
+/// about as much as on a heatmap and every key width of travel adds to that.
+pub fn get_travel_effort_map(
+	heatmap_weight: f64, keyboard: &Keyboard, shape: LayoutShape, fingering: &[usize]
+) -> Vec<f64> {
+	TravelModel::new(keyboard, shape, fingering)
+		.key_costs(fingering)
+		.into_iter()
+		.map(|cost| (0.8 + 0.6 * cost) / 4.5 * heatmap_weight)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QWERTY: &str = "qwertyuiopasdfghjkl;zxcvbnm,./";
+
+	#[test]
+	fn travel() {
+		let shape = LayoutShape::default();
+		let qwerty = FastLayout::try_from(QWERTY).unwrap();
+		let ortho = Keyboard::load("ortho").unwrap();
+		let model = TravelModel::new(&ortho, shape, &qwerty.fingering).with_drift(1.0);
+
+		let home = model.simulate_text(&qwerty, "asdf jkl;");
+		assert_eq!(home.presses, 8.0);
+		assert_eq!(home.total(), 0.0);
+
+		// there to e and back when pressing s
+		let e = model.simulate_text(&qwerty, "es");
+		assert_eq!(e.fingers[2], 2.0);
+		assert_eq!(e.total(), 2.0);
+
+		let costs = model.key_costs(&qwerty.fingering);
+		assert_eq!(costs[13], 0.0);
+		assert_eq!(costs[3], 2.0);
+		assert!((costs[4] - 2.0 * 2f64.sqrt()).abs() < 1e-9);
+
+		assert_eq!(Keyboard::default().home_keys(shape, &qwerty.fingering)[4], Some(16));
+		let data = LanguageData::from_file("static/language_data", "english").unwrap();
+		let colemak = FastLayout::try_from("qwfpgjluy;arstdhneiozxcvbkm,./").unwrap();
+		let model = TravelModel::new(&Keyboard::default(), shape, &qwerty.fingering);
+		assert!(model.simulate_ngrams(&colemak, &data).per_press() < model.simulate_ngrams(&qwerty, &data).per_press());
+	}
+}
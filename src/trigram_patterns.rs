@@ -1,3 +1,8 @@
+use itertools::iproduct;
+
+use crate::utility::{FINGER_COUNT, LEFT_THUMB, RIGHT_THUMB, LayoutShape};
+use crate::weights::Weights;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TrigramPattern {
 	Alternate,
@@ -27,11 +32,23 @@ impl std::fmt::Display for Trigram {
 }
 
 impl Trigram {
+	/// Orders fingers from left to right so the thumbs end up between both index fingers, which
+	/// keeps the inward/outward comparisons below working when a thumb is involved.
+	const fn position(finger: usize) -> usize {
+		match finger {
+			LEFT_THUMB => 4,
+			RIGHT_THUMB => 5,
+			f if f >= 4 => f + 2,
+			f => f
+		}
+	}
+
 	const fn lh(col: usize) -> bool {
-		col < 4
+		col < 5
 	}
 
-	const fn new(c1: usize, c2: usize, c3: usize) -> Self {
+	const fn new(f1: usize, f2: usize, f3: usize) -> Self {
+		let (c1, c2, c3) = (Self::position(f1), Self::position(f2), Self::position(f3));
 		Trigram {
 			c1, c2, c3, lh1: Self::lh(c1), lh2: Self::lh(c2), lh3: Self::lh(c3)
 		}
@@ -86,8 +103,13 @@ impl Trigram {
 		(self.c1 < self.c2 && self.c2 > self.c3) || (self.c1 > self.c2 && self.c2 < self.c3)
 	}
 
+	const fn is_strong(c: usize) -> bool {
+		c >= 3 && c <= 6
+	}
+
+	/// A redirect without an index finger or thumb in it.
 	const fn is_bad_redir(&self) -> bool {
-		!(self.c1 == 3 || self.c2 == 3 || self.c3 == 3 || self.c1 == 4 || self.c2 == 4 || self.c3 == 4)
+		!(Self::is_strong(self.c1) || Self::is_strong(self.c2) || Self::is_strong(self.c3))
 	}
 
 	const fn has_sfb(&self) -> bool {
@@ -132,16 +154,19 @@ impl Trigram {
 	}
 }
 
-const fn get_trigram_combinations() -> [TrigramPattern; 512] {
-	let mut combinations: [TrigramPattern; 512] = [TrigramPattern::Other; 512];
+/// Every finger fits in 4 bits, so a trigram of fingers is indexed by `f1 << 8 | f2 << 4 | f3`.
+pub const TRIGRAM_COMBINATION_COUNT: usize = 1 << 12;
+
+const fn get_trigram_combinations() -> [TrigramPattern; TRIGRAM_COMBINATION_COUNT] {
+	let mut combinations = [TrigramPattern::Other; TRIGRAM_COMBINATION_COUNT];
 
 	let mut c3 = 0;
-	while c3 < 8 {
+	while c3 < FINGER_COUNT {
 		let mut c2 = 0;
-		while c2 < 8 {
+		while c2 < FINGER_COUNT {
 			let mut c1 = 0;
-			while c1 < 8 {
-				let index = c3*64 + c2*8 + c1;
+			while c1 < FINGER_COUNT {
+				let index = (c3 << 8) | (c2 << 4) | c1;
 				let trigram = Trigram::new(c1, c2, c3);
 				combinations[index] = trigram.get_trigram_pattern();
 				c1 += 1;
@@ -153,7 +178,239 @@ const fn get_trigram_combinations() -> [TrigramPattern; 512] {
 	combinations
 }
 
-pub static TRIGRAM_COMBINATIONS: [TrigramPattern; 512] = get_trigram_combinations();
+pub static TRIGRAM_COMBINATIONS: [TrigramPattern; TRIGRAM_COMBINATION_COUNT] =
+	get_trigram_combinations();
+
+/// What the rules of a trigram class know about a key: its hand, 0 for the left one and 1 for the
+/// right one, its finger counted from the outside of the hand, 0 for the pinky up to 3 for the
+/// index and 4 for the thumb, and its row, 0 being the top row of the layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyInfo {
+	pub hand: i64,
+	pub finger: i64,
+	pub row: i64
+}
+
+impl KeyInfo {
+	pub const fn new(finger: usize, row: usize) -> Self {
+		let (hand, finger) = match finger {
+			LEFT_THUMB => (0, 4),
+			RIGHT_THUMB => (1, 4),
+			f if f < 4 => (0, f),
+			f => (1, 7 - f)
+		};
+		Self { hand, finger: finger as i64, row: row as i64 }
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+	/// `h`, `f` or `r` of the first, second or third key.
+	Key(char, usize),
+	Int(i64)
+}
+
+impl Operand {
+	fn value(&self, keys: &[KeyInfo; 3]) -> i64 {
+		match *self {
+			Self::Key('h', k) => keys[k].hand,
+			Self::Key('f', k) => keys[k].finger,
+			Self::Key(_, k) => keys[k].row,
+			Self::Int(i) => i
+		}
+	}
+}
+
+impl TryFrom<&str> for Operand {
+	type Error = anyhow::Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		if let Ok(i) = value.parse::<i64>() {
+			return Ok(Self::Int(i))
+		}
+		let mut chars = value.chars();
+		match (chars.next(), chars.next(), chars.next()) {
+			(Some(var @ ('h' | 'f' | 'r')), Some(k @ '1'..='3'), None) => {
+				Ok(Self::Key(var, k as usize - '1' as usize))
+			},
+			_ => anyhow::bail!("'{value}' isn't a number or one of h, f and r followed by 1, 2 or 3")
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+	Eq, Ne, Lt, Le, Gt, Ge
+}
+
+/// A comparison like `f2 - f1 == 1`, kept as the terms of its left side minus its right side.
+#[derive(Clone, Debug, PartialEq)]
+struct Comparison {
+	terms: Vec<(i64, Operand)>,
+	op: Op
+}
+
+impl Comparison {
+	fn holds(&self, keys: &[KeyInfo; 3]) -> bool {
+		let diff = self.terms.iter().map(|(sign, o)| sign * o.value(keys)).sum::<i64>();
+		match self.op {
+			Op::Eq => diff == 0,
+			Op::Ne => diff != 0,
+			Op::Lt => diff < 0,
+			Op::Le => diff <= 0,
+			Op::Gt => diff > 0,
+			Op::Ge => diff >= 0
+		}
+	}
+
+	/// Adds the terms of a sum like `f2 - f1 + 1`, with their sign flipped when `sign` is -1.
+	fn add_terms(&mut self, side: &str, sign: i64) -> anyhow::Result<()> {
+		let mut term_sign = sign;
+		let mut operand = String::new();
+
+		for c in side.chars().chain(std::iter::once('+')) {
+			match c {
+				'+' | '-' => {
+					if operand.is_empty() {
+						anyhow::bail!("'{}' is missing a term", side.trim());
+					}
+					self.terms.push((term_sign, Operand::try_from(operand.as_str())?));
+					operand.clear();
+					term_sign = if c == '-' { -sign } else { sign };
+				},
+				c if c.is_whitespace() => {},
+				c => operand.push(c)
+			}
+		}
+		Ok(())
+	}
+}
+
+impl TryFrom<&str> for Comparison {
+	type Error = anyhow::Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		const OPS: [(&str, Op); 6] = [
+			("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)
+		];
+
+		let Some((lhs, rhs, op)) = OPS.iter()
+			.find_map(|(s, op)| value.split_once(s).map(|(lhs, rhs)| (lhs, rhs, *op))) else {
+			anyhow::bail!("'{}' doesn't compare anything, use one of ==, !=, <, <=, > and >=", value.trim())
+		};
+
+		let mut res = Self { terms: Vec::new(), op };
+		res.add_terms(lhs, 1)?;
+		res.add_terms(rhs, -1)?;
+		Ok(res)
+	}
+}
+
+/// Comparisons joined by `&&`, which all have to hold for the rule to match a trigram.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule(Vec<Comparison>);
+
+impl Rule {
+	pub fn matches(&self, keys: &[KeyInfo; 3]) -> bool {
+		self.0.iter().all(|c| c.holds(keys))
+	}
+}
+
+impl TryFrom<&str> for Rule {
+	type Error = anyhow::Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		value.split("&&")
+			.map(Comparison::try_from)
+			.collect::<anyhow::Result<Vec<_>>>()
+			.map(Self)
+	}
+}
+
+/// A kind of trigram, which a trigram is when one of its rules matches it. Its weight is added to
+/// the score for every trigram of the class, so negative weights are penalties.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrigramClass {
+	pub name: String,
+	pub weight: f64,
+	pub rules: Vec<Rule>
+}
+
+impl TrigramClass {
+	pub fn new(name: &str, weight: f64, rules: &[&str]) -> anyhow::Result<Self> {
+		let rules = rules.iter()
+			.map(|&r| Rule::try_from(r))
+			.collect::<anyhow::Result<Vec<_>>>()
+			.map_err(|e| anyhow::anyhow!("class {name}: {e}"))?;
+
+		Ok(Self { name: name.to_string(), weight, rules })
+	}
+
+	pub fn matches(&self, keys: &[KeyInfo; 3]) -> bool {
+		self.rules.iter().any(|r| r.matches(keys))
+	}
+}
+
+/// Trigram classes in the order they're tried, every trigram being of the first one that matches
+/// it. Trigrams no class matches are left out of the score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrigramTaxonomy {
+	pub classes: Vec<TrigramClass>
+}
+
+impl TrigramTaxonomy {
+	pub fn new(classes: Vec<TrigramClass>) -> anyhow::Result<Self> {
+		if let Some((i, class)) = classes.iter()
+			.enumerate()
+			.find(|(i, c)| classes[..*i].iter().any(|prev| prev.name == c.name)) {
+			anyhow::bail!("class {} is defined twice, the second time as class {}", class.name, i + 1)
+		}
+		Ok(Self { classes })
+	}
+
+	/// The same classes as `TrigramPattern`, weighted with the trigram weights of the config.
+	pub fn from_weights(weights: &Weights) -> Self {
+		macro_rules! one_hand {
+			($rule:literal) => { concat!("h1 == h2 && h2 == h3 && ", $rule) }
+		}
+
+		let classes: [(&str, f64, &[&str]); 10] = [
+			("Alternates (sfs)", weights.alternates_sfs, &["h1 != h2 && h2 != h3 && f1 == f3"]),
+			("Alternates", weights.alternates, &["h1 != h2 && h2 != h3"]),
+			("Sft", 0.0, &[one_hand!("f1 == f2 && f2 == f3")]),
+			("Bad Sfbs", 0.0, &[one_hand!("f1 == f2"), one_hand!("f2 == f3")]),
+			("Bad Redirects", -weights.bad_redirects, &[
+				one_hand!("f1 < f2 && f2 > f3 && f1 < 3 && f2 < 3 && f3 < 3"),
+				one_hand!("f1 > f2 && f2 < f3 && f1 < 3 && f2 < 3 && f3 < 3")
+			]),
+			("Redirects", -weights.redirects, &[one_hand!("f1 < f2 && f2 > f3"), one_hand!("f1 > f2 && f2 < f3")]),
+			("Onehands", weights.onehands, &[one_hand!("f1 < f2 && f2 < f3"), one_hand!("f1 > f2 && f2 > f3")]),
+			("Sfbs", 0.0, &["h1 == h2 && f1 == f2", "h2 == h3 && f2 == f3"]),
+			("Inrolls", weights.inrolls, &["h1 == h2 && h2 != h3 && f1 < f2", "h1 != h2 && h2 == h3 && f2 < f3"]),
+			("Outrolls", weights.outrolls, &["h1 == h2 && h2 != h3 && f1 > f2", "h1 != h2 && h2 == h3 && f2 > f3"])
+		];
+
+		let classes = classes.into_iter()
+			.map(|(name, weight, rules)| TrigramClass::new(name, weight, rules).unwrap())
+			.collect();
+		Self { classes }
+	}
+
+	pub fn classify(&self, keys: &[KeyInfo; 3]) -> Option<usize> {
+		self.classes.iter().position(|c| c.matches(keys))
+	}
+
+	/// The class of every trigram of keys on a layout, indexed by `(k1 * len + k2) * len + k3`.
+	pub fn classify_keys(&self, shape: LayoutShape, fingering: &[usize]) -> Vec<Option<usize>> {
+		let keys = (0..shape.len())
+			.map(|i| KeyInfo::new(fingering[i], shape.i_to_xy(i).1))
+			.collect::<Vec<_>>();
+
+		iproduct!(&keys, &keys, &keys)
+			.map(|(&k1, &k2, &k3)| self.classify(&[k1, k2, k3]))
+			.collect()
+	}
+}
 
 #[cfg(test)]
 mod tests {
@@ -181,4 +438,71 @@ mod tests {
 		assert_eq!(dvorak.get_trigram_pattern(&['g', 'h', 't']), BadSfb);
 		assert_eq!(dvorak.get_trigram_pattern(&['p', 'u', 'k']), Sft);
     }
-}
\ No newline at end of file
+
+	#[test]
+	fn thumb_trigrams() {
+		use layout::{FastLayout, Layout};
+		use trigram_patterns::TrigramPattern::*;
+		use utility::LayoutShape;
+
+		let shape = LayoutShape::new(3, 10).with_thumbs(2);
+		let chars = "qw1rtyuiopasdfghjkl;zxcvbnm,./e ".chars().collect::<Vec<_>>();
+		let layout = FastLayout::from((chars, shape, shape.default_fingering()));
+
+		assert_eq!(layout.char_to_finger.get(&'e'), Some(&utility::LEFT_THUMB));
+		assert_eq!(layout.char_to_finger.get(&' '), Some(&utility::RIGHT_THUMB));
+
+		assert_eq!(layout.get_trigram_pattern(&['a', ' ', 's']), Alternate);
+		assert_eq!(layout.get_trigram_pattern(&[' ', 'h', 'k']), Onehand);
+		assert_eq!(layout.get_trigram_pattern(&['s', 'e', 'h']), Inroll);
+		assert_eq!(layout.get_trigram_pattern(&['h', 'e', 's']), Outroll);
+		assert_eq!(layout.get_trigram_pattern(&['s', 'a', 'e']), Redirect);
+	}
+
+	#[test]
+	fn taxonomy() {
+		use trigram_patterns::*;
+		use trigram_patterns::TrigramPattern::*;
+
+		let taxonomy = TrigramTaxonomy::from_weights(&weights::Config::default().weights);
+		let name = |pattern| match pattern {
+			Alternate => Some("Alternates"),
+			AlternateSfs => Some("Alternates (sfs)"),
+			Inroll => Some("Inrolls"),
+			Outroll => Some("Outrolls"),
+			Onehand => Some("Onehands"),
+			Redirect => Some("Redirects"),
+			BadRedirect => Some("Bad Redirects"),
+			Sfb => Some("Sfbs"),
+			BadSfb => Some("Bad Sfbs"),
+			Sft => Some("Sft"),
+			Other | Invalid => None
+		};
+
+		for (f1, f2, f3) in itertools::iproduct!(0..FINGER_COUNT, 0..FINGER_COUNT, 0..FINGER_COUNT) {
+			let keys = [KeyInfo::new(f1, 0), KeyInfo::new(f2, 0), KeyInfo::new(f3, 0)];
+			let class = taxonomy.classify(&keys).map(|c| taxonomy.classes[c].name.as_str());
+
+			assert_eq!(class, name(TRIGRAM_COMBINATIONS[(f1 << 8) | (f2 << 4) | f3]), "{f1} {f2} {f3}");
+		}
+
+		let adjacent = TrigramClass::new(
+			"Adjacent inrolls", 2.5, &["h1 == h2 && h2 != h3 && f2 - f1 == 1 && r1 == r2"]
+		).unwrap();
+		let taxonomy = TrigramTaxonomy::new(vec![adjacent]).unwrap();
+		let classes = taxonomy.classify_keys(utility::LayoutShape::default(), &utility::LayoutShape::default().default_fingering());
+		let index = |[k1, k2, k3]: [usize; 3]| (k1 * 30 + k2) * 30 + k3;
+
+		assert_eq!(classes.len(), 30 * 30 * 30);
+		assert_eq!(classes[index([11, 12, 15])], Some(0));
+		assert_eq!(classes[index([10, 12, 15])], None);
+		assert_eq!(classes[index([1, 12, 15])], None);
+		assert_eq!(classes[index([12, 11, 15])], None);
+
+		assert!(Rule::try_from("f1 < f2 &&").is_err());
+		assert!(Rule::try_from("f4 == 1").is_err());
+		assert!(Rule::try_from("f1 + == 1").is_err());
+		assert!(Rule::try_from("f1 f2").is_err());
+		assert!(TrigramTaxonomy::new(vec![taxonomy.classes[0].clone(), taxonomy.classes[0].clone()]).is_err());
+	}
+}
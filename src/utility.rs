@@ -1,10 +1,14 @@
 use crate::languages_cfg::read_cfg;
+use crate::keyboard::Keyboard;
 
 use itertools::Itertools;
 use serde::Deserialize;
 use arrayvec::ArrayVec;
 use nanorand::{Rng, tls_rng};
 
+/// Upper bound on the amount of keys a layout can have, used to size stack buffers.
+pub const MAX_KEYS: usize = 64;
+
 #[inline]
 pub fn shuffle_pins<const N: usize, T>(slice: &mut [T], pins: &[usize]) {
     let mapping: ArrayVec<_, N> = (0..slice.len()).filter(|x| !pins.contains(x)).collect();
@@ -17,21 +21,219 @@ pub fn shuffle_pins<const N: usize, T>(slice: &mut [T], pins: &[usize]) {
 }
 
 pub static COL_TO_FINGER: [usize; 10] = [0, 1, 2, 3, 3, 4, 4, 5, 6, 7];
-pub static I_TO_COL: [usize; 30] = [
-	0, 1, 2, 3, 3,  4, 4, 5, 6, 7,
-	0, 1, 2, 3, 3,  4, 4, 5, 6, 7,
-	0, 1, 2, 3, 3,  4, 4, 5, 6, 7
-];
+
+/// Fingers 0-3 are the left pinky to index, 4-7 the right index to pinky. The thumbs come last.
+pub const LEFT_THUMB: usize = 8;
+pub const RIGHT_THUMB: usize = 9;
+pub const FINGER_COUNT: usize = 10;
+
+#[inline(always)]
+pub const fn is_left_hand(finger: usize) -> bool {
+	finger < 4 || finger == LEFT_THUMB
+}
+
+/// Which finger presses each key, by key index.
+pub type Fingering = Vec<usize>;
+
+/// Parses a fingering written as one finger per key, separated by whitespace and laid out like a
+/// `.kb` file.
+pub fn parse_fingering(fingering_str: &str, shape: LayoutShape) -> anyhow::Result<Fingering> {
+	let fingering = fingering_str
+		.split_whitespace()
+		.map(|f| f.parse::<usize>())
+		.collect::<Result<Fingering, _>>()?;
+
+	if fingering.len() != shape.len() {
+		anyhow::bail!("a {shape} fingering needs {} fingers, not {}", shape.len(), fingering.len())
+	}
+	if let Some(f) = fingering.iter().find(|&&f| f >= FINGER_COUNT) {
+		anyhow::bail!("finger {f} doesn't exist, fingers go from 0 to {}", FINGER_COUNT - 1)
+	}
+	Ok(fingering)
+}
+
+/// Parses a grid of one character per key, laid out like a `.kb` file with the thumb keys on a row
+/// of their own. Whitespace is left out, and an empty grid has no keys.
+pub fn parse_key_grid(grid: &str, shape: LayoutShape) -> anyhow::Result<Vec<char>> {
+	let keys = grid.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+
+	if !keys.is_empty() && keys.len() != shape.len() {
+		anyhow::bail!("a {shape} layout has {} keys, not {}", shape.len(), keys.len())
+	}
+	Ok(keys)
+}
+
+/// The dimensions of a layout. Every shape contains the standard 3x10 alpha block; extra columns
+/// are outer pinky columns and a fourth row is a number row above the alpha block. Thumb keys come
+/// after the grid, the first half of them on the left thumb.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutShape {
+	pub rows: usize,
+	pub cols: usize,
+	#[serde(default)]
+	pub thumbs: usize
+}
+
+impl Default for LayoutShape {
+	fn default() -> Self {
+		Self::new(3, 10)
+	}
+}
+
+impl TryFrom<&str> for LayoutShape {
+	type Error = anyhow::Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		let lower = value.trim().to_lowercase();
+		let (grid, thumbs) = lower.split_once('+').unwrap_or((lower.as_str(), "0"));
+
+		if let Some((rows, cols)) = grid.split_once('x') {
+			let rows = rows.trim().parse::<usize>()?;
+			let cols = cols.trim().parse::<usize>()?;
+			let thumbs = thumbs.trim().parse::<usize>()?;
+
+			if (3..=4).contains(&rows) && (10..=12).contains(&cols) && [0, 2, 4].contains(&thumbs) {
+				return Ok(Self::new(rows, cols).with_thumbs(thumbs))
+			}
+		}
+		anyhow::bail!("'{value}' is not a supported shape, use 3x10 up to 4x12 with +2 or +4 thumb keys")
+	}
+}
+
+impl std::fmt::Display for LayoutShape {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}x{}", self.rows, self.cols)?;
+		if self.thumbs > 0 {
+			write!(f, "+{}", self.thumbs)?;
+		}
+		Ok(())
+	}
+}
+
+impl LayoutShape {
+	pub const fn new(rows: usize, cols: usize) -> Self {
+		Self { rows, cols, thumbs: 0 }
+	}
+
+	pub const fn with_thumbs(self, thumbs: usize) -> Self {
+		Self { thumbs, ..self }
+	}
+
+	/// Infers the shape from the amount of keys, which is unambiguous for every supported shape
+	/// without thumb keys.
+	pub fn from_len(len: usize) -> Option<Self> {
+		[(3, 10), (3, 11), (3, 12), (4, 10), (4, 11), (4, 12)]
+			.into_iter()
+			.map(|(rows, cols)| Self::new(rows, cols))
+			.find(|shape| shape.len() == len)
+	}
+
+	#[inline(always)]
+	pub const fn len(&self) -> usize {
+		self.grid_len() + self.thumbs
+	}
+
+	#[inline(always)]
+	pub const fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Amount of keys excluding the thumb keys.
+	#[inline(always)]
+	pub const fn grid_len(&self) -> usize {
+		self.rows * self.cols
+	}
+
+	#[inline(always)]
+	pub const fn is_thumb(&self, i: usize) -> bool {
+		i >= self.grid_len()
+	}
+
+	/// Column the 3x10 alpha block starts at. A 12 column board has an extra pinky column on both
+	/// sides, an 11 column board only on the right.
+	#[inline(always)]
+	pub const fn col_offset(&self) -> usize {
+		if self.cols == 12 { 1 } else { 0 }
+	}
+
+	/// Row the 3x10 alpha block starts at, which is 1 when there's a number row.
+	#[inline(always)]
+	pub const fn row_offset(&self) -> usize {
+		self.rows - 3
+	}
+
+	/// First column that belongs to the right hand.
+	#[inline(always)]
+	pub const fn hand_split(&self) -> usize {
+		self.col_offset() + 5
+	}
+
+	#[inline]
+	pub const fn col_to_finger(&self, col: usize) -> usize {
+		let offset = self.col_offset();
+		if col < offset {
+			COL_TO_FINGER[0]
+		} else if col - offset > 9 {
+			COL_TO_FINGER[9]
+		} else {
+			COL_TO_FINGER[col - offset]
+		}
+	}
+
+	/// The fingering every key gets unless configured otherwise, based on its column.
+	pub fn default_fingering(&self) -> Fingering {
+		(0..self.len()).map(|i| self.i_to_finger(i)).collect()
+	}
+
+	#[inline]
+	pub const fn i_to_finger(&self, i: usize) -> usize {
+		if self.is_thumb(i) {
+			if i - self.grid_len() < self.thumbs / 2 { LEFT_THUMB } else { RIGHT_THUMB }
+		} else {
+			self.col_to_finger(i % self.cols)
+		}
+	}
+
+	/// Column and row of a key. Thumb keys sit on an extra row below the grid, centered on the
+	/// split between both hands.
+	#[inline]
+	pub const fn i_to_xy(&self, i: usize) -> (usize, usize) {
+		if self.is_thumb(i) {
+			(self.hand_split() + i - self.grid_len() - self.thumbs / 2, self.rows)
+		} else {
+			(i % self.cols, i / self.cols)
+		}
+	}
+
+	/// Maps an index on a 3x10 layout to the same key in the alpha block of this shape.
+	#[inline]
+	pub const fn main_index(&self, i: usize) -> usize {
+		(i / 10 + self.row_offset()) * self.cols + i % 10 + self.col_offset()
+	}
+
+	/// Row of a key in the alpha block, 0 for the top row up to 2 for the bottom one. `None` for the
+	/// number row and the thumb keys.
+	#[inline]
+	pub const fn alpha_row(&self, i: usize) -> Option<usize> {
+		let row = i / self.cols;
+		if self.is_thumb(i) || row < self.row_offset() {
+			None
+		} else {
+			Some(row - self.row_offset())
+		}
+	}
+
+	pub fn possible_swaps(&self) -> Vec<PosPair> {
+		(0..self.len())
+			.tuple_combinations()
+			.map(|(pos1, pos2)| PosPair(pos1, pos2))
+			.collect()
+	}
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct PosPair(pub usize, pub usize);
 
-const AFFECTS_SCISSOR: [bool; 30] = [
-	true,  true,  true,  true,  true,   true,  true,  true,  true,  true,
-	true,  true,  false, false, false,  false, false, false, true,  true,
-	true,  true,  true,  false, true,   false, false, true,  true,  true
-];
-
 impl PosPair {
 	pub const fn default() -> Self {
 		Self(0, 0)
@@ -41,13 +243,6 @@ impl PosPair {
 		Self(x1, x2)
 	}
 
-	#[inline]
-	pub fn affects_scissor(&self) -> bool {
-		unsafe {
-			*AFFECTS_SCISSOR.get_unchecked(self.0) || *AFFECTS_SCISSOR.get_unchecked(self.1)
-		}
-	}
-
 	pub fn qwerty_pos(c: char) -> usize {
 		match c {
 		  'q' => 0,
@@ -95,197 +290,147 @@ impl std::fmt::Display for PosPair {
     }
 }
 
-pub static POSSIBLE_SWAPS: [PosPair; 435] = get_possible_swaps();
-
-const fn get_possible_swaps() -> [PosPair; 435] {
-	let mut res = [PosPair::default(); 435];
-	let mut i = 0;
-	let mut pos1 = 0;
-	
-	while pos1 < 30 {
-		let mut pos2 = pos1 + 1;
-		while pos2 < 30 {
-			res[i] = PosPair(pos1, pos2);
-			i += 1;
-			pos2 += 1;
-		}
-		pos1 += 1;
-	}
-	res
+/// Efforts of the keys of a shape on a keyboard, scaled by the heatmap weight.
+pub fn get_effort_map(heatmap_weight: f64, keyboard: &Keyboard, shape: LayoutShape) -> Vec<f64> {
+	keyboard.keys(shape)
+		.iter()
+		.map(|key| (key.effort - 0.2) / 4.5 * heatmap_weight)
+		.collect()
 }
 
-#[derive(Deserialize, Debug)]
-pub enum KeyboardType {
-	AnsiAngle,
-	IsoAngle,
-	RowstagDefault,
-	Ortho,
-	Colstag
+/// Every pair of keys typed by the same finger with how far apart they are, lateral distance
+/// weighted by `lat_multiplier`, times the speed multiplier of the finger.
+pub fn get_fspeed(
+    lat_multiplier: f64, finger_speed: &[f64; FINGER_COUNT], keyboard: &Keyboard, shape: LayoutShape,
+    fingering: &[usize]
+) -> Vec<(PosPair, f64)> {
+    let sfb_indices = get_sfb_indices(fingering);
+    let distances = get_distances(
+        lat_multiplier, finger_speed, &keyboard.key_positions(shape), fingering, &sfb_indices
+    );
+
+    sfb_indices.into_iter().zip(distances).collect()
 }
 
-impl TryFrom<String> for KeyboardType {
-    type Error = &'static str;
+fn get_distances(
+    lat_multiplier: f64, finger_speed: &[f64; FINGER_COUNT], positions: &[(f64, f64)], fingering: &[usize],
+    sfb_indices: &[PosPair]
+) -> Vec<f64> {
+    sfb_indices
+        .iter()
+        .map(|&PosPair(i1, i2)| {
+            let (x1, y1) = positions[i1];
+            let (x2, y2) = positions[i2];
+            let x_dist = x1 - x2;
+            let y_dist = y1 - y2;
+
+            (x_dist.powi(2)*lat_multiplier + y_dist.powi(2)).powf(0.65) * finger_speed[fingering[i1]]
+        })
+        .collect()
+}
 
-    fn try_from(value: String) -> Result<Self, &'static str> {
-		let lower = value.to_lowercase();
-		let split = lower.split_whitespace().collect::<Vec<&str>>();
+/// All pairs of keys typed by the same finger, ordered by finger.
+pub fn get_sfb_indices(fingering: &[usize]) -> Vec<PosPair> {
+	let mut res: Vec<PosPair> = Vec::new();
+	for finger in 0..FINGER_COUNT {
+		let keys = (0..fingering.len())
+			.filter(|&i| fingering[i] == finger)
+			.collect::<Vec<_>>();
 
-        if split.len() == 1 {
-			match split[0] {
-				"ortho" => Ok(Self::Ortho),
-				"colstag" => Ok(Self::Colstag),
-				"rowstag" | "iso" | "ansi" | "jis" => Ok(Self::RowstagDefault),
-				_ => Err("Couldn't parse keyboard type!")
-			}
-		} else if split.len() == 2 {
-			match (split[0], split[1]) {
-				("ansi", "angle") => Ok(Self::AnsiAngle),
-				("iso", "angle") => Ok(Self::IsoAngle),
-				_ => Err("Couldn't parse keyboard type!")
-			}
-		} else {
-			Err("Couldn't parse keyboard type!")
+		for c in keys.into_iter().combinations(2) {
+			res.push(PosPair(c[0], c[1]));
 		}
-    }
-}
-
-pub fn get_effort_map(heatmap_weight: f64, ktype: KeyboardType) -> [f64; 30] {
-	use KeyboardType::*;
-	
-	let mut res = match ktype {
-		IsoAngle => [
-			3.0, 2.4, 2.0, 2.2, 2.4,  3.3, 2.2, 2.0, 2.4, 3.0,
-			1.8, 1.3, 1.1, 1.0, 2.6,  2.6, 1.0, 1.1, 1.3, 1.8,
-			3.3, 2.8, 2.4, 1.8, 2.2,  2.2, 1.8, 2.4, 2.8, 3.3
-		],
-		AnsiAngle => [
-			3.0, 2.4, 2.0, 2.2, 2.4,  3.3, 2.2, 2.0, 2.4, 3.0,
-			1.8, 1.3, 1.1, 1.0, 2.6,  2.6, 1.0, 1.1, 1.3, 1.8,
-			3.7, 2.8, 2.4, 1.8, 2.2,  2.2, 1.8, 2.4, 2.8, 3.3
-		],
-		RowstagDefault => [
-			3.0, 2.4, 2.0, 2.2, 2.4,  3.3, 2.2, 2.0, 2.4, 3.0,
-			1.8, 1.3, 1.1, 1.0, 2.6,  2.6, 1.0, 1.1, 1.3, 1.8,
-			3.5, 3.0, 2.7, 2.2, 3.7,  2.2, 1.8, 2.4, 2.8, 3.3
-		],
-		Ortho => [
-			3.0, 2.4, 2.0, 2.2, 3.1,  3.1, 2.2, 2.0, 2.4, 3.0,
-			1.7, 1.3, 1.1, 1.0, 2.6,  2.6, 1.0, 1.1, 1.3, 1.7,
-			3.2, 2.6, 2.3, 1.6, 3.0,  3.0, 1.6, 2.3, 2.6, 3.2
-		],
-		Colstag => [
-			3.0, 2.4, 2.0, 2.2, 3.1,  3.1, 2.2, 2.0, 2.4, 3.0,
-			1.7, 1.3, 1.1, 1.0, 2.6,  2.6, 1.0, 1.1, 1.3, 1.7,
-			3.4, 2.7, 2.2, 1.8, 3.2,  3.2, 1.8, 2.2, 2.7, 3.4
-		],
-	};
-	
-	for i in 0..res.len() {
-		res[i] -= 0.2;
-		res[i] /= 4.5;
-		res[i] *= heatmap_weight;
 	}
-
 	res
 }
 
-pub fn get_fspeed(lat_multiplier: f64) -> [(PosPair, f64); 48] {
-    let mut res = Vec::new();
-    for (b, dist) in get_sfb_indices().iter().zip(get_distances(lat_multiplier)) {
-        res.push((*b, dist));
-    }
-    res.try_into().unwrap()
+/// All pairs of keys on the top and bottom row of the alpha block that are typed by different
+/// fingers of the same hand.
+pub fn get_row_jump_indices(shape: LayoutShape, fingering: &[usize]) -> Vec<PosPair> {
+	(0..shape.len())
+		.tuple_combinations()
+		.filter(|&(i1, i2)| {
+			let (f1, f2) = (fingering[i1], fingering[i2]);
+			matches!((shape.alpha_row(i1), shape.alpha_row(i2)), (Some(0), Some(2)) | (Some(2), Some(0)))
+				&& f1 != f2 && is_left_hand(f1) == is_left_hand(f2)
+		})
+		.map(|(i1, i2)| PosPair(i1, i2))
+		.collect()
 }
 
-fn get_distances(lat_multiplier: f64) -> [f64; 48] {
-    let mut res = Vec::new();
-    let help = |f: f64, r: f64| f.powi(2).powf(0.65) * r;
-    
-    for fweight in [1.4, 3.6, 4.8, 4.8, 3.6, 1.4] {
-		let ratio = 5.5/fweight;
-        res.append(&mut vec![help(1.0, ratio), help(2.0, ratio), help(1.0, ratio)]);
-    }
+/// How long every finger is compared to the others on its hand, from the pinky to the index.
+const FINGER_LENGTH: [usize; 4] = [0, 2, 3, 1];
 
-    for _ in 0..2 {
-        for c in [
-			(0, (0i32, 0)), (1, (0i32, 1)), (2, (0, 2)), (3, (1, 0)), (4, (1, 1)), (5, (1, 2))
-		].iter().combinations(2) {
-            let (_, xy1) = c[0];
-            let (_, xy2) = c[1];
-
-			let x_dist = (xy1.0 - xy2.0) as f64;
-			let y_dist = (xy1.1 - xy2.1) as f64;
-			let distance = (x_dist.powi(2)*lat_multiplier + y_dist.powi(2)).powf(0.65);
-			
-			res.push(distance);
-        }
-    }
-    res.try_into().unwrap()
+/// Whether two fingers are next to each other on the same hand, leaving out the thumbs.
+const fn adjacent_fingers(f1: usize, f2: usize) -> bool {
+	f1 < LEFT_THUMB && f2 < LEFT_THUMB && f1.abs_diff(f2) == 1 && is_left_hand(f1) == is_left_hand(f2)
 }
 
-pub fn get_sfb_indices() -> [PosPair; 48] {
-	let mut res: Vec<PosPair> = Vec::new();
-	for i in [0, 1, 2, 7, 8, 9] {
-		let chars = [i, i+10, i+20];
-		for c in chars.into_iter().combinations(2) {
-			res.push(PosPair(c[0], c[1]));
-		}
-	}
-	for i in [0, 2] {
-		let chars = [3+i, 13+i, 23+i, 4+i, 14+i, 24+i];
-		for c in chars.into_iter().combinations(2) {
-			res.push(PosPair(c[0], c[1]));
-		}
-	}
-	res.try_into().unwrap()
+/// How many rows two keys on adjacent fingers need to be apart, relative to the home keys of those
+/// fingers, to be a scissor, and how many when the shorter finger is the higher one.
+const FULL_SCISSOR: f64 = 1.5;
+const HALF_SCISSOR: f64 = 0.75;
+
+/// All pairs of keys on adjacent fingers of one hand where one finger reaches up and the other
+/// down: two rows apart, or a row apart with the shorter finger higher up. Rows are counted from
+/// the home key of every finger, so column stagger is taken into account.
+pub fn get_scissor_indices(keyboard: &Keyboard, shape: LayoutShape, fingering: &[usize]) -> Vec<PosPair> {
+	let positions = keyboard.key_positions(shape);
+	let homes = keyboard.home_keys(shape, fingering);
+	let length = |f: usize| FINGER_LENGTH[if f < 4 { f } else { 7 - f }];
+
+	(0..fingering.len())
+		.tuple_combinations()
+		.filter(|&(i1, i2)| adjacent_fingers(fingering[i1], fingering[i2]))
+		.filter(|&(i1, i2)| {
+			let (f1, f2) = (fingering[i1], fingering[i2]);
+			let (Some(h1), Some(h2)) = (homes[f1], homes[f2]) else {
+				return false
+			};
+			// how much higher the first key is than the second
+			let dy = (positions[i2].1 - positions[h2].1) - (positions[i1].1 - positions[h1].1);
+			let shorter_higher = if length(f1) < length(f2) { dy } else { -dy };
+
+			dy.abs() >= FULL_SCISSOR || shorter_higher >= HALF_SCISSOR
+		})
+		.map(|(i1, i2)| PosPair(i1, i2))
+		.collect()
 }
 
-pub fn get_scissor_indices() -> [PosPair; 28] {
-	let mut res: Vec<PosPair> = Vec::new();
-	//these two are top pinky to ring homerow
-	res.push(PosPair::from_qwerty('q','s'));
-	res.push(PosPair::from_qwerty('p','l'));
-	//these two are pinky home to ring bottom
-	res.push(PosPair::from_qwerty('a', 'x'));
-	res.push(PosPair::from_qwerty(';', '.'));
-	//these four are inner index stretches
-	res.push(PosPair::from_qwerty('e', 'b'));
-	res.push(PosPair::from_qwerty('e', 'g'));
-	res.push(PosPair::from_qwerty('c', 't'));
-	res.push(PosPair::from_qwerty('y', ','));
-	//these add normal stretching between ajacent columns that stretch between 2 rows except for
-	//qwerty mi and cr (assuming c is typed with index)
-	res.push(PosPair::from_qwerty('q', 'x'));
-	res.push(PosPair::from_qwerty('w', 'z'));
-	res.push(PosPair::from_qwerty('w', 'c'));
-	res.push(PosPair::from_qwerty('e', 'x'));
-	res.push(PosPair::from_qwerty('r', 'c'));
-	res.push(PosPair::from_qwerty('u', ','));
-	res.push(PosPair::from_qwerty('i', '.'));
-	res.push(PosPair::from_qwerty('o', ','));
-	res.push(PosPair::from_qwerty('o', '/'));
-	res.push(PosPair::from_qwerty('p', '.'));
-	res.push(PosPair::from_qwerty('f', 'c'));
-	res.push(PosPair::from_qwerty('d', 't'));
-	res.push(PosPair::from_qwerty('y', 'k'));
-	res.push(PosPair::from_qwerty('d', 'r'));
-	res.push(PosPair::from_qwerty('u', 'k'));
-	res.push(PosPair::from_qwerty('d', 'b'));
-	res.push(PosPair::from_qwerty('s', 'r'));
-	res.push(PosPair::from_qwerty('s', 't'));
-	res.push(PosPair::from_qwerty('s', 'b'));
-	res.push(PosPair::from_qwerty('w', 'b'));
-	res.try_into().unwrap()
+/// How much further apart than their home keys two keys on adjacent fingers need to be sideways,
+/// in key widths, for a bigram between them to be a lateral stretch.
+const LSB_STRETCH: f64 = 1.0;
+
+/// All pairs of keys on adjacent fingers of one hand that are at least `LSB_STRETCH` further apart
+/// sideways than the home keys of those fingers, like the middle finger top row and the index
+/// finger center column.
+pub fn get_lsb_indices(keyboard: &Keyboard, shape: LayoutShape, fingering: &[usize]) -> Vec<PosPair> {
+	let positions = keyboard.key_positions(shape);
+	let homes = keyboard.home_keys(shape, fingering);
+
+	(0..fingering.len())
+		.tuple_combinations()
+		.filter(|&(i1, i2)| adjacent_fingers(fingering[i1], fingering[i2]))
+		.filter(|&(i1, i2)| {
+			let (Some(h1), Some(h2)) = (homes[fingering[i1]], homes[fingering[i2]]) else {
+				return false
+			};
+			let spread = (positions[i1].0 - positions[i2].0).abs();
+			let home_spread = (positions[h1].0 - positions[h2].0).abs();
+			spread - home_spread >= LSB_STRETCH
+		})
+		.map(|(i1, i2)| PosPair(i1, i2))
+		.collect()
 }
 
-pub fn chars_for_generation(language: &str) -> [char; 30] {
+pub fn chars_for_generation(language: &str) -> Vec<char> {
 	let languages_cfg_map = read_cfg();
 
 	if let Some(cfg) = languages_cfg_map.get(language) {
-		cfg.chars().collect::<Vec<char>>().try_into().unwrap()
+		cfg.chars().collect()
 	} else {
 		let default = languages_cfg_map.get(&String::from("default")).unwrap();
-		default.chars().collect::<Vec<char>>().try_into().unwrap()
+		default.chars().collect()
 	}
 }
 
@@ -333,22 +478,140 @@ pub(crate) fn layout_name(entry: &std::fs::DirEntry) -> Option<String> {
 	None
 }
 
-pub(crate) fn format_layout_str(layout_str: String) -> String {
-	layout_str
-		.split("\n")
-		.take(3)
-		.map(|line| {
-			line.split_whitespace()
-				.take(10)
-				.collect::<String>()
-		})
-		.collect::<String>()
+/// Reads the key rows at the start of a `.kb` file, stopping at the first empty line. A shorter
+/// last row holds the thumb keys, where `_` stands for space. Returns the keys as a single string
+/// along with the shape they were written in.
+pub(crate) fn format_layout_str(layout_str: String) -> Option<(String, LayoutShape)> {
+	let mut rows = layout_str
+		.lines()
+		.map(|line| line.split_whitespace().collect::<Vec<_>>())
+		.take_while(|line| !line.is_empty())
+		.take(5)
+		.collect::<Vec<_>>();
+
+	let cols = rows.first()?.len();
+	let thumbs = match rows.last() {
+		Some(last) if rows.len() > 1 && last.len() < cols => last.len(),
+		_ => 0
+	};
+	let grid_rows = rows.len() - if thumbs > 0 { 1 } else { 0 };
+
+	if rows[..grid_rows].iter().any(|row| row.len() != cols) {
+		return None
+	}
+	let shape = LayoutShape::try_from(format!("{grid_rows}x{cols}+{thumbs}").as_str()).ok()?;
+
+	if let Some(thumb_row) = rows.get_mut(grid_rows) {
+		for key in thumb_row.iter_mut() {
+			if *key == "_" {
+				*key = " ";
+			}
+		}
+	}
+	
+	Some((rows.concat().concat(), shape))
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn shapes() {
+		let default = LayoutShape::default();
+		let ortho = Keyboard::load("ortho").unwrap();
+		assert_eq!(default.possible_swaps().len(), 435);
+		assert_eq!(get_sfb_indices(&default.default_fingering()).len(), 48);
+		let scissors = get_scissor_indices(&ortho, default, &default.default_fingering());
+		// per hand 2 full and 2 half scissors between the pinky and ring and the ring and middle
+		// finger, and between the middle finger and both index finger columns
+		assert_eq!(scissors.len(), 2 * (4 + 4 + 2 * 4));
+		assert!(scissors.contains(&PosPair(0, 11)) && scissors.contains(&PosPair(2, 24)));
+		assert!(!scissors.contains(&PosPair(12, 13)) && !scissors.contains(&PosPair(2, 14)));
+		assert_eq!(get_row_jump_indices(default, &default.default_fingering()).len(), 2 * (25 - 7));
+		assert_eq!(default.alpha_row(12), Some(1));
+		let lsbs = get_lsb_indices(&ortho, default, &default.default_fingering());
+		assert_eq!(lsbs.len(), 2 * 9);
+		assert!(lsbs.contains(&PosPair(2, 4)) && lsbs.contains(&PosPair(15, 27)));
+
+		let big = LayoutShape::try_from("3x12").unwrap();
+		assert_eq!(big.main_index(0), 1);
+		assert_eq!(big.main_index(29), 34);
+		assert_eq!(big.i_to_finger(0), 0);
+		assert_eq!(big.i_to_finger(35), 7);
+		assert_eq!(get_sfb_indices(&big.default_fingering()).len(), 48 + 2*(15 - 3));
+		assert_eq!(get_effort_map(1.0, &ortho, big).len(), 36);
+		assert_eq!(get_lsb_indices(&ortho, big, &big.default_fingering()).len(), 4 * 9);
+
+		assert_eq!(LayoutShape::from_len(40), Some(LayoutShape::new(4, 10)));
+		assert!(LayoutShape::try_from("2x10").is_err());
+		assert!(LayoutShape::try_from("3x13").is_err());
+		assert!(LayoutShape::try_from("3x10+3").is_err());
+		assert_eq!(LayoutShape::try_from("4x12+4").unwrap().len(), 52);
+		assert_eq!(LayoutShape::try_from("4x12+4").unwrap().alpha_row(11), None);
+		assert_eq!(LayoutShape::try_from("4x12+4").unwrap().alpha_row(47), Some(2));
+		assert_eq!(LayoutShape::try_from("4x12+4").unwrap().alpha_row(48), None);
+	}
+
+	#[test]
+	fn fingering() {
+		let shape = LayoutShape::default();
+		let angle = parse_fingering("
+			0 1 2 3 3  4 4 5 6 7
+			0 1 2 3 3  4 4 5 6 7
+			1 2 3 3 3  4 4 5 6 7
+		", shape).unwrap();
+		
+		let sfbs = get_sfb_indices(&angle);
+		// the left pinky loses 2 pairs, the left index gains 6
+		assert_eq!(sfbs.len(), 52);
+		assert!(sfbs.contains(&PosPair(1, 20)));
+		assert!(!sfbs.contains(&PosPair(0, 20)));
+
+		assert_eq!(Keyboard::load("ansi angle").unwrap().fingering(shape), angle);
+		// the angle mod of an ISO keyboard moves keys instead of fingers
+		let iso = Keyboard::load("iso angle").unwrap();
+		assert_eq!(iso.fingering(shape), shape.default_fingering());
+		assert_eq!(iso.key_positions(shape)[20], (-0.25, 2.0));
+		assert_eq!(Keyboard::load("ortho").unwrap().fingering(shape), shape.default_fingering());
+
+		let big = LayoutShape::new(4, 12).with_thumbs(2);
+		let big_angle = Keyboard::load("ansi angle").unwrap().fingering(big);
+		assert_eq!(&big_angle[36..42], &[0, 1, 2, 3, 3, 3]);
+		assert_eq!(&big_angle[48..], &[LEFT_THUMB, RIGHT_THUMB]);
+
+		assert!(parse_fingering("0 1 2", shape).is_err());
+		assert!(parse_fingering(&"10 ".repeat(30), shape).is_err());
+	}
+
+	#[test]
+	fn key_grid() {
+		let shape = LayoutShape::new(4, 10).with_thumbs(2);
+		let grid = "..... .....\nx.... .....\n..... .....\n..... .....\n.x";
+		let keys = parse_key_grid(grid, shape).unwrap();
+		assert_eq!(keys.iter().positions(|&c| c == 'x').collect::<Vec<_>>(), [10, 41]);
+
+		assert!(parse_key_grid("", shape).unwrap().is_empty());
+		assert!(parse_key_grid("..... .....\n..... .....\n..... .....", shape).is_err());
+	}
+
+	#[test]
+	fn kb_file_shape() {
+		let (keys, shape) = format_layout_str(
+			"1 2 3 4 5  6 7 8 9 0\nq w e r t  y u i o p\na s d f g  h j k l ;\nz x c v b  n m , . /\n\nextra".to_string()
+		).unwrap();
+		assert_eq!(shape, LayoutShape::new(4, 10));
+		assert_eq!(keys.chars().count(), 40);
+		assert!(format_layout_str("q w e\na s".to_string()).is_none());
+
+		let (keys, shape) = format_layout_str(
+			"q w e r t  y u i o p\na s d f g  h j k l ;\nz x c v b  n m , . /\n        e  _".to_string()
+		).unwrap();
+		assert_eq!(shape, LayoutShape::new(3, 10).with_thumbs(2));
+		assert!(keys.ends_with("/e "));
+		assert_eq!(shape.i_to_xy(31), (5, 3));
+	}
+
 	#[test]
 	fn shuffle_pinned() {
 		let mut rng = tls_rng();
@@ -1,20 +1,47 @@
 use serde::Deserialize;
 use std::fs::File;
 use std::io::Read;
-use crate::utility::KeyboardType;
+use crate::utility::{LayoutShape, Fingering, parse_fingering, parse_key_grid, FINGER_COUNT};
+use crate::keyboard::Keyboard;
+use crate::language_data::{RepeatKey, SKIP_DISTANCES};
+use crate::trigram_patterns::{TrigramClass, TrigramTaxonomy};
+use crate::constraints::{Constraint, Rule, Hand};
+use crate::travel::EffortModel;
+use crate::generate::annealing::{Annealing, Optimizer};
 
 #[derive(Deserialize, Debug)]
 pub struct WeightDefaultsLoad {
 	pub language: String,
 	pub keyboard_type: String,
-	trigram_precision: usize
+	trigram_precision: usize,
+	#[serde(default = "default_shape")]
+	pub shape: String,
+	#[serde(default)]
+	pub effort: EffortModel,
+	#[serde(default)]
+	pub optimizer: Optimizer,
+	#[serde(default)]
+	pub space: bool
 }
 
-#[derive(Deserialize, Debug)]
+fn default_shape() -> String {
+	"3x10".to_string()
+}
+
+#[derive(Debug)]
 pub struct WeightDefaults {
 	pub language: String,
-	pub keyboard_type: KeyboardType,
-	pub trigram_precision: usize
+	/// The keyboard named by `keyboard_type`, from `static/keyboards`.
+	pub keyboard: Keyboard,
+	pub trigram_precision: usize,
+	pub shape: LayoutShape,
+	/// What the effort of every key is based on.
+	pub effort: EffortModel,
+	/// How layouts are generated.
+	pub optimizer: Optimizer,
+	/// Whether to use the language data with space, `{language}_space`, so space gets a key of its
+	/// own. Only shapes with thumb keys can have it.
+	pub space: bool
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -23,7 +50,76 @@ pub struct MaxFingerUse {
 	pub pinky: f64,
 	pub ring: f64,
 	pub middle: f64,
-	pub index: f64
+	pub index: f64,
+	#[serde(default = "default_thumb_use")]
+	pub thumb: f64
+}
+
+fn default_thumb_use() -> f64 {
+	100.0
+}
+
+/// How much skipgrams count compared to sfbs, by how many characters they skip.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "model", rename_all = "lowercase")]
+pub enum SkipgramDecay {
+	/// The ratio of every skip distance, from 1 on. Distances without one don't count.
+	Values { values: Vec<f64> },
+	/// `dsfb_ratio` for skipping one character, multiplied by `decay` for every one more.
+	Exponential { decay: f64 },
+	/// `dsfb_ratio` divided by the skip distance to the power of `exponent`.
+	Power { exponent: f64 }
+}
+
+impl Default for SkipgramDecay {
+	fn default() -> Self {
+		Self::Exponential { decay: 0.4 }
+	}
+}
+
+/// How much sfbs on every finger count, which multiplies the distance between their keys. `base`
+/// multiplies all of them.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FingerSpeed {
+	pub base: f64,
+	pub pinky: f64,
+	pub ring: f64,
+	pub middle: f64,
+	pub index: f64,
+	#[serde(default = "default_thumb_speed")]
+	pub thumb: f64
+}
+
+fn default_thumb_speed() -> f64 {
+	1.375
+}
+
+impl Default for FingerSpeed {
+	fn default() -> Self {
+		Self { base: 1.0, pinky: 5.5 / 1.4, ring: 5.5 / 3.6, middle: 5.5 / 4.8, index: 1.0, thumb: 5.5 / 4.0 }
+	}
+}
+
+impl FingerSpeed {
+	/// The multiplier of every finger, without `base`.
+	pub fn per_finger(&self) -> [f64; FINGER_COUNT] {
+		let Self { pinky, ring, middle, index, thumb, .. } = *self;
+		[pinky, ring, middle, index, index, middle, ring, pinky, thumb, thumb]
+	}
+}
+
+/// Penalty for using the top or bottom row more than a share of all key presses.
+#[derive(Deserialize, Clone, Debug)]
+pub struct MaxRowUse {
+	pub penalty: f64,
+	pub top: f64,
+	pub bottom: f64
+}
+
+impl Default for MaxRowUse {
+	fn default() -> Self {
+		Self { penalty: 0.0, top: 100.0, bottom: 100.0 }
+	}
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -33,10 +129,11 @@ pub struct Weights {
 	pub fspeed: f64,
 	pub dsfb_ratio: f64,
 	#[serde(default)]
-	pub dsfb_ratio2: f64,
-	#[serde(default)]
-	pub dsfb_ratio3: f64,
+	pub skipgram_decay: SkipgramDecay,
 	pub scissors: f64,
+	/// Penalty for lateral stretch bigrams, between adjacent fingers spread further than usual.
+	#[serde(default)]
+	pub lsbs: f64,
 	pub inrolls: f64,
 	pub outrolls: f64,
 	pub onehands: f64,
@@ -44,12 +141,130 @@ pub struct Weights {
 	pub alternates_sfs: f64,
 	pub redirects: f64,
 	pub bad_redirects: f64,
-	pub max_finger_use: MaxFingerUse
+	/// Cost of every combo press on top of the effort of its keys.
+	#[serde(default = "default_combo")]
+	pub combo: f64,
+	/// Penalty for the difference between how much both hands are used.
+	#[serde(default)]
+	pub hand_balance: f64,
+	pub max_finger_use: MaxFingerUse,
+	#[serde(default)]
+	pub max_row_use: MaxRowUse,
+	#[serde(default)]
+	pub finger_speed: FingerSpeed
+}
+
+fn default_combo() -> f64 {
+	2.0
+}
+
+impl Weights {
+	/// How much the skipgrams of every skip distance count compared to sfbs.
+	pub fn dsfb_ratios(&self) -> [f64; SKIP_DISTANCES] {
+		std::array::from_fn(|i| {
+			let distance = (i + 1) as f64;
+			match &self.skipgram_decay {
+				SkipgramDecay::Values { values } => values.get(i).copied().unwrap_or_default(),
+				SkipgramDecay::Exponential { decay } => self.dsfb_ratio * decay.powf(distance - 1.0),
+				SkipgramDecay::Power { exponent } => self.dsfb_ratio / distance.powf(*exponent)
+			}
+		})
+	}
+}
+
+#[derive(Deserialize, Default)]
+struct RepeatKeyLoad {
+	#[serde(default)]
+	pub key: String,
+	#[serde(default)]
+	pub magic: String
+}
+
+impl RepeatKeyLoad {
+	/// The repeat key, if there is one. `magic` is made of pairs of characters, the one typed
+	/// before the key and the one the key types after it.
+	fn parse(&self) -> Result<Option<RepeatKey>, String> {
+		let mut key = self.key.trim().chars();
+		let key = match (key.next(), key.next()) {
+			(None, _) => return Ok(None),
+			(Some(c), None) => c,
+			_ => return Err(format!("the repeat key should be a single character, not '{}'", self.key))
+		};
+
+		let mut magic = fxhash::FxHashMap::default();
+		for pair in self.magic.split_whitespace() {
+			match pair.chars().collect::<Vec<_>>()[..] {
+				[prev, typed] => magic.insert(prev, typed),
+				_ => return Err(format!("'{pair}' should be the character before the key and what it types"))
+			};
+		}
+		Ok(Some(RepeatKey::new(key).with_magic(magic)))
+	}
+}
+
+#[derive(Deserialize)]
+struct ConstraintLoad {
+	pub rule: String,
+	#[serde(default)]
+	pub chars: String,
+	#[serde(default)]
+	pub fingers: Vec<usize>,
+	#[serde(default)]
+	pub hand: String,
+	#[serde(default)]
+	pub count: usize,
+	pub penalty: Option<f64>
+}
+
+impl ConstraintLoad {
+	fn parse(&self) -> Result<Constraint, String> {
+		let chars = self.chars.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+		if chars.is_empty() {
+			return Err(format!("the {} rule doesn't have any chars", self.rule))
+		}
+		let hand = || Hand::try_from(self.hand.as_str()).map_err(|e| e.to_string());
+
+		let rule = match self.rule.trim() {
+			"fingers" => match self.fingers.iter().find(|&&f| f >= FINGER_COUNT) {
+				Some(f) => return Err(format!("there is no finger {f}")),
+				None => Rule::Fingers(chars, self.fingers.clone())
+			},
+			"same hand" => Rule::SameHand(chars),
+			"different hands" => match chars[..] {
+				[c1, c2] => Rule::DifferentHands(c1, c2),
+				_ => return Err(format!("different hands is about two chars, not '{}'", self.chars))
+			},
+			"max on hand" => Rule::MaxOnHand(chars, hand()?, self.count),
+			rule => return Err(format!(
+				"'{rule}' isn't a rule, use fingers, same hand, different hands or max on hand"
+			))
+		};
+		Ok(Constraint { rule, penalty: self.penalty })
+	}
+}
+
+#[derive(Deserialize)]
+struct TrigramClassLoad {
+	pub name: String,
+	pub weight: f64,
+	pub rules: Vec<String>
 }
 
 #[derive(Deserialize)]
 struct ConfigLoad {
 	pub pins: String,
+	#[serde(default)]
+	pub fingering: String,
+	#[serde(default)]
+	pub repeat_key: RepeatKeyLoad,
+	#[serde(default)]
+	pub combos: String,
+	#[serde(default)]
+	pub trigram_classes: Vec<TrigramClassLoad>,
+	#[serde(default)]
+	pub constraints: Vec<ConstraintLoad>,
+	#[serde(default)]
+	pub annealing: Annealing,
 	pub defaults: WeightDefaultsLoad,
 	pub weights: Weights
 }
@@ -65,13 +280,24 @@ impl ConfigLoad {
 
 		let mut res: Self = toml::from_slice(&buf)
 			.expect("Failed to parse config.toml. Values might be missing.");
-		res.pins = res.pins.trim().replace(' ', "").replace('\n', "");
 		res
 	}
 }
 
 pub struct Config {
 	pub pins: Vec<usize>,
+	/// Overrides the finger each key is pressed with. `None` uses the columns of the shape.
+	pub fingering: Option<Fingering>,
+	/// A key that types the previous character again, which is placed like any other.
+	pub repeat_key: Option<RepeatKey>,
+	/// Sets of keys that type a character of their own when pressed together.
+	pub combos: Vec<Vec<usize>>,
+	/// Classes trigrams are scored by. `None` uses the built-in ones with the trigram weights.
+	pub trigram_classes: Option<TrigramTaxonomy>,
+	/// Rules for where characters go.
+	pub constraints: Vec<Constraint>,
+	/// Settings of the annealing optimizer.
+	pub annealing: Annealing,
 	pub defaults: WeightDefaults,
 	pub weights: Weights
 }
@@ -86,22 +312,89 @@ impl Config {
 			ring: load.weights.max_finger_use.ring / 100.0,
 			middle: load.weights.max_finger_use.middle / 100.0,
 			index: load.weights.max_finger_use.index / 100.0,
+			thumb: load.weights.max_finger_use.thumb / 100.0,
 		};
-		let mut pins = Vec::new();
-		for (i, c) in load.pins.chars().enumerate() {
-			if c == 'x' {
-				pins.push(i);
+		load.weights.max_row_use.top /= 100.0;
+		load.weights.max_row_use.bottom /= 100.0;
+		if let SkipgramDecay::Values { values } = &load.weights.skipgram_decay
+			&& values.len() > SKIP_DISTANCES {
+			panic!(
+				"Failed to parse the skipgram decay in config.toml: there are only {SKIP_DISTANCES} skip distances, not {}",
+				values.len()
+			);
+		}
+
+		let shape = LayoutShape::try_from(load.defaults.shape.as_str())
+			.expect("Failed to parse the shape in config.toml");
+		let pins = parse_key_grid(&load.pins, shape)
+			.expect("Failed to parse the pins in config.toml")
+			.into_iter()
+			.enumerate()
+			.filter_map(|(i, c)| (c == 'x').then_some(i))
+			.collect();
+		if load.defaults.space && shape.thumbs == 0 {
+			panic!("Failed to parse the space setting in config.toml: a {shape} layout has no thumb key to put space on");
+		}
+		let fingering = if load.fingering.trim().is_empty() {
+			None
+		} else {
+			Some(parse_fingering(&load.fingering, shape)
+				.expect("Failed to parse the fingering in config.toml"))
+		};
+
+		let repeat_key = load.repeat_key.parse()
+			.expect("Failed to parse the repeat key in config.toml");
+
+		// keys marked with the same character make up a combo
+		let mut combos = indexmap::IndexMap::<char, Vec<usize>>::new();
+		let combo_keys = parse_key_grid(&load.combos, shape)
+			.expect("Failed to parse the combos in config.toml");
+		for (i, c) in combo_keys.into_iter().enumerate() {
+			if c != '.' {
+				combos.entry(c).or_default().push(i);
 			}
 		}
-		load.weights.dsfb_ratio2 = (load.weights.dsfb_ratio * 6.0).powi(3) / 6.5;
-		load.weights.dsfb_ratio3 = (load.weights.dsfb_ratio * 6.0).powi(5) / 7.0;
+		let combos = combos.into_values().collect::<Vec<_>>();
+		if let Some(combo) = combos.iter().find(|keys| keys.len() < 2) {
+			panic!("Failed to parse the combos in config.toml: key {} is a combo on its own", combo[0]);
+		}
+
+		let trigram_classes = if load.trigram_classes.is_empty() {
+			None
+		} else {
+			let classes = load.trigram_classes.iter()
+				.map(|c| {
+					let rules = c.rules.iter().map(|r| r.as_str()).collect::<Vec<_>>();
+					TrigramClass::new(&c.name, c.weight, &rules)
+				})
+				.collect::<anyhow::Result<Vec<_>>>()
+				.and_then(TrigramTaxonomy::new)
+				.expect("Failed to parse the trigram classes in config.toml");
+			Some(classes)
+		};
+
+		let constraints = load.constraints.iter()
+			.map(|c| c.parse())
+			.collect::<Result<Vec<_>, _>>()
+			.expect("Failed to parse the constraints in config.toml");
+
 		Self {
 			pins,
+			fingering,
+			repeat_key,
+			combos,
+			trigram_classes,
+			constraints,
+			annealing: load.annealing,
 			defaults: WeightDefaults {
 				language: load.defaults.language,
-				keyboard_type: KeyboardType::try_from(load.defaults.keyboard_type)
-					.unwrap_or(KeyboardType::AnsiAngle),
-				trigram_precision: load.defaults.trigram_precision
+				keyboard: Keyboard::load(&load.defaults.keyboard_type)
+					.expect("Failed to load the keyboard_type in config.toml"),
+				trigram_precision: load.defaults.trigram_precision,
+				shape,
+				effort: load.defaults.effort,
+				optimizer: load.defaults.optimizer,
+				space: load.defaults.space
 			},
 			weights: load.weights
 		}
@@ -111,17 +404,21 @@ impl Config {
 		Self {
 			defaults: WeightDefaults {
 				language: "english".to_string(),
-				keyboard_type: KeyboardType::AnsiAngle,
-				trigram_precision: 1000
+				keyboard: Keyboard::default(),
+				trigram_precision: 1000,
+				shape: LayoutShape::default(),
+				effort: EffortModel::Heatmap,
+				optimizer: Optimizer::HillClimbing,
+				space: false
 			},
 			weights: Weights {
 				heatmap: 0.85,
 				lateral_penalty: 1.3,
 				fspeed: 8.0,
 				dsfb_ratio: 0.12,
-				dsfb_ratio2: (0.10 * 6.0f64).powi(2),
-				dsfb_ratio3: (0.08 * 6.0f64).powi(3),
+				skipgram_decay: SkipgramDecay::default(),
 				scissors: 5.0,
+				lsbs: 0.0,
 				inrolls: 1.6,
 				outrolls: 1.3,
 				onehands: 0.8,
@@ -129,15 +426,30 @@ impl Config {
 				alternates_sfs: 0.35,
 				redirects: 1.5,
 				bad_redirects: 6.5,
+				combo: 2.0,
+				hand_balance: 0.0,
 				max_finger_use: MaxFingerUse {
 					penalty: 2.5,
 					pinky: 9.0,
 					ring: 16.0,
 					middle: 19.5,
-					index: 18.0
-				}
+					index: 18.0,
+					thumb: 100.0
+				},
+				max_row_use: MaxRowUse {
+					penalty: 0.0,
+					top: 0.25,
+					bottom: 0.15
+				},
+				finger_speed: FingerSpeed::default()
 			},
 			pins: Vec::new(),
+			fingering: None,
+			repeat_key: None,
+			combos: Vec::new(),
+			trigram_classes: None,
+			constraints: Vec::new(),
+			annealing: Annealing::default()
 		}
 	}
 